@@ -2,14 +2,47 @@ export type Settings = {
   paths: string[];
   ongen_limit: number;
   ongen_settings: Record<string, OngenSettings>;
+  write_frq: boolean;
+  sample_cache_limit: number;
+};
+
+export type SampleCacheStats = {
+  entries: number;
+  size: number;
+  limit: number;
+  hits: number;
+  misses: number;
+  evictions: number;
+};
+
+export type VoicebankType = "cv" | "vcv" | "cvvc" | "mixed";
+
+export const voicebankTypeNames: Record<VoicebankType, string> = {
+  cv: "単独音",
+  vcv: "連続音",
+  cvvc: "CVVC",
+  mixed: "混在",
 };
 
 export type OngenSettings = {
   name: string | null;
+  voicebank_type: VoicebankType | null;
+  alias_fallbacks: AliasFallback[];
   portrait: string | null;
   style_settings: StyleSettings[];
 };
 
+export type AliasFallback = {
+  from: string;
+  to: string;
+};
+
+export type FallbackReportItem = {
+  text: string;
+  alias: string | null;
+  fallback: string | null;
+};
+
 export type StyleSettings = {
   name: string;
   portrait: string | null;
@@ -23,10 +56,40 @@ export type StyleSettings = {
   tension: number;
   peak_compression: number;
   voicing: number;
+  breath: boolean;
+
+  oto_folders: string[];
+  alias_prefix: string;
+  alias_suffix: string;
+  curve_rules: CurveRule[];
+};
+
+export type CurveKind = "gender" | "tension" | "breathiness" | "voicing";
+export type CurveTarget = "phrase_start" | "phrase_end" | "accent";
+
+export const curveKindNames: Record<CurveKind, string> = {
+  gender: "性別",
+  tension: "声の張り",
+  breathiness: "息の強さ",
+  voicing: "声の強さ",
+};
+
+export const curveTargetNames: Record<CurveTarget, string> = {
+  phrase_start: "フレーズの最初",
+  phrase_end: "フレーズの最後",
+  accent: "アクセント核",
+};
+
+export type CurveRule = {
+  curve: CurveKind;
+  target: CurveTarget;
+  value: number;
 };
 
 export type Ongen = {
   name: string;
+  voicebank_type: VoicebankType;
+  oto_folders: string[];
 };
 
 const createUse =
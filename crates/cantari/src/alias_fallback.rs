@@ -0,0 +1,132 @@
+//! エイリアスが見つからない時に、代わりに使う読みの表。
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+
+/// 組み込みの代替の読み。同じ読みの中では前にあるものが優先される。
+static BUILTIN_FALLBACKS: &[(&str, &str)] = &[
+    // 小さい仮名
+    ("ぁ", "あ"),
+    ("ぃ", "い"),
+    ("ぅ", "う"),
+    ("ぇ", "え"),
+    ("ぉ", "お"),
+    ("ゃ", "や"),
+    ("ゅ", "ゆ"),
+    ("ょ", "よ"),
+    ("ゎ", "わ"),
+    // 同じ音の仮名
+    ("を", "お"),
+    ("お", "を"),
+    ("ぢ", "じ"),
+    ("じ", "ぢ"),
+    ("づ", "ず"),
+    ("ず", "づ"),
+    // 外来語の音
+    ("ゔ", "ぶ"),
+    ("ゔぁ", "ば"),
+    ("ゔぃ", "び"),
+    ("ゔぇ", "べ"),
+    ("ゔぉ", "ぼ"),
+    ("ゔゅ", "びゅ"),
+    ("てぃ", "ち"),
+    ("でぃ", "じ"),
+    ("てゅ", "ちゅ"),
+    ("でゅ", "じゅ"),
+    ("とぅ", "つ"),
+    ("どぅ", "ず"),
+    ("ふぁ", "は"),
+    ("ふぃ", "ひ"),
+    ("ふぇ", "へ"),
+    ("ふぉ", "ほ"),
+    ("ふゅ", "ひゅ"),
+    ("うぃ", "い"),
+    ("うぇ", "え"),
+    ("うぉ", "お"),
+    ("いぇ", "え"),
+    ("つぁ", "ちゃ"),
+    ("つぃ", "ち"),
+    ("つぇ", "ちぇ"),
+    ("つぉ", "ちょ"),
+    ("ちぇ", "せ"),
+    ("しぇ", "せ"),
+    ("じぇ", "ぜ"),
+    ("くぁ", "か"),
+    ("ぐぁ", "が"),
+    ("すぃ", "し"),
+    ("ずぃ", "じ"),
+];
+
+/// 音源毎に設定する代替の読み。組み込みのものより優先される。
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AliasFallback {
+    pub from: String,
+    pub to: String,
+}
+
+/// 音源毎の設定と組み込みの表を重ねたもの。
+#[derive(Debug, Clone, Copy)]
+pub struct FallbackTable<'a> {
+    overrides: &'a [AliasFallback],
+}
+
+impl<'a> FallbackTable<'a> {
+    pub fn new(overrides: &'a [AliasFallback]) -> Self {
+        Self { overrides }
+    }
+
+    fn direct(&self, kana: &str) -> Vec<&'a str> {
+        self.overrides
+            .iter()
+            .filter(|fallback| fallback.from == kana)
+            .map(|fallback| fallback.to.as_str())
+            .chain(
+                BUILTIN_FALLBACKS
+                    .iter()
+                    .filter(|(from, _)| *from == kana)
+                    .map(|(_, to)| *to),
+            )
+            .collect()
+    }
+
+    /// `kana`の代わりに使う読みを、近いものから順に返す。`kana`自身は含まない。
+    pub fn candidates(&self, kana: &str) -> Vec<String> {
+        let mut visited = HashSet::from([kana.to_string()]);
+        let mut queue = VecDeque::from([kana.to_string()]);
+        let mut candidates = vec![];
+        while let Some(current) = queue.pop_front() {
+            for next in self.direct(&current) {
+                if visited.insert(next.to_string()) {
+                    candidates.push(next.to_string());
+                    queue.push_back(next.to_string());
+                }
+            }
+        }
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        let table = FallbackTable::new(&[]);
+        assert_eq!(table.candidates("を"), vec!["お"]);
+        assert_eq!(table.candidates("どぅ"), vec!["ず", "づ"]);
+        assert!(table.candidates("あ").is_empty());
+
+        let overrides = [
+            AliasFallback {
+                from: "ゔぁ".to_string(),
+                to: "ふぁ".to_string(),
+            },
+            AliasFallback {
+                from: "は".to_string(),
+                to: "ゔぁ".to_string(),
+            },
+        ];
+        let table = FallbackTable::new(&overrides);
+        assert_eq!(table.candidates("ゔぁ"), vec!["ふぁ", "ば", "は"]);
+    }
+}
@@ -0,0 +1,109 @@
+//! エイリアスの表記（ひらがな・カタカナ・ローマ字）の判定と変換。
+use crate::mora_table;
+use regex_macro::regex;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// エイリアスの表記。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AliasScript {
+    /// ひらがな（`か`、`a か`）
+    Hiragana,
+    /// カタカナ（`カ`、`a カ`）
+    Katakana,
+    /// ローマ字（`ka`、`a ka`）
+    Romaji,
+}
+
+impl AliasScript {
+    /// エイリアスの一覧から表記を推定する。同数の場合はひらがなとする。
+    pub fn detect<'a>(aliases: impl IntoIterator<Item = &'a str>) -> Self {
+        let hiragana_pattern = regex!(r"^[ぁ-ゖ]");
+        let katakana_pattern = regex!(r"^[ァ-ヺ]");
+        // 子音だけのもの（CVVCの`a k`など）は数えない
+        let romaji_pattern = regex!(r"^[a-z]*[aiueo]");
+
+        let mut counts: HashMap<Self, usize> = HashMap::new();
+        for alias in aliases {
+            // 連続音の前の母音（`a か`の`a`）は表記によらないので、最後の部分だけを見る
+            let kana = alias.rsplit(' ').next().unwrap_or(alias);
+            let script = if hiragana_pattern.is_match(kana) {
+                Self::Hiragana
+            } else if katakana_pattern.is_match(kana) {
+                Self::Katakana
+            } else if romaji_pattern.is_match(kana) {
+                Self::Romaji
+            } else {
+                continue;
+            };
+            *counts.entry(script).or_default() += 1;
+        }
+
+        [Self::Hiragana, Self::Katakana, Self::Romaji]
+            .into_iter()
+            .max_by_key(|script| {
+                (
+                    counts.get(script).copied().unwrap_or(0),
+                    *script == Self::Hiragana,
+                )
+            })
+            .unwrap()
+    }
+
+    /// 複数のフォルダの表記をまとめる。優先されるフォルダのものを使う。
+    pub fn merge(scripts: impl IntoIterator<Item = Self>) -> Self {
+        scripts.into_iter().last().unwrap_or(Self::Hiragana)
+    }
+
+    /// ひらがなの読みを、この表記に変換する。変換できないものはそのまま返す。
+    pub fn convert(self, kana: &str) -> String {
+        match self {
+            Self::Hiragana => kana.to_string(),
+            Self::Katakana => kana::hira2kata(kana),
+            Self::Romaji => to_romaji(kana).unwrap_or_else(|| kana.to_string()),
+        }
+    }
+}
+
+/// モーラの音素から、ローマ字の読みを作る。
+fn to_romaji(kana: &str) -> Option<String> {
+    let mora = mora_table::find_by_text(&kana::hira2kata(kana))?;
+    let vowel = match mora.vowel {
+        "N" => "n",
+        vowel => vowel,
+    };
+    Some(format!("{}{}", mora.consonant.unwrap_or(""), vowel))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_alias_script() {
+        assert_eq!(
+            AliasScript::detect(["- か", "a か", "a k", "か"]),
+            AliasScript::Hiragana
+        );
+        assert_eq!(
+            AliasScript::detect(["- カ", "a カ", "a k", "カ"]),
+            AliasScript::Katakana
+        );
+        assert_eq!(
+            AliasScript::detect(["- ka", "a ka", "a k", "ka", "あ"]),
+            AliasScript::Romaji
+        );
+        assert_eq!(AliasScript::detect([]), AliasScript::Hiragana);
+    }
+
+    #[test]
+    fn test_convert() {
+        assert_eq!(AliasScript::Hiragana.convert("しゃ"), "しゃ");
+        assert_eq!(AliasScript::Katakana.convert("しゃ"), "シャ");
+        assert_eq!(AliasScript::Romaji.convert("しゃ"), "sha");
+        assert_eq!(AliasScript::Romaji.convert("ん"), "n");
+        assert_eq!(AliasScript::Romaji.convert("つ"), "tsu");
+        assert_eq!(AliasScript::Romaji.convert("、"), "、");
+    }
+}
@@ -0,0 +1,15 @@
+use std::path::PathBuf;
+
+use once_cell::sync::Lazy;
+
+/// 解析結果などを保存するフォルダ。`TEMPDIR`と違い、終了しても消さない。
+pub static CACHE_DIR: Lazy<PathBuf> = Lazy::new(|| {
+    let name = if cfg!(not(debug_assertions)) {
+        "cantari"
+    } else {
+        "cantari-dev"
+    };
+    dirs::cache_dir()
+        .unwrap_or_else(|| dirs::home_dir().unwrap().join(".cache"))
+        .join(name)
+});
@@ -0,0 +1,109 @@
+//! OpenUtauのcharacter.yaml。
+use crate::math::MidiNote;
+use serde::Deserialize;
+use std::{collections::HashMap, str::FromStr};
+
+/// character.yamlのうち、Cantariで使う項目。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CharacterYaml {
+    /// character.txtやoto.iniの文字コード。
+    pub text_file_encoding: Option<String>,
+    pub name: Option<String>,
+    pub image: Option<String>,
+    pub portrait: Option<String>,
+    /// 立ち絵の不透明度（0〜1）。
+    pub portrait_opacity: Option<f32>,
+    pub author: Option<String>,
+    pub voice: Option<String>,
+    pub web: Option<String>,
+    pub version: Option<String>,
+    pub default_phonemizer: Option<String>,
+    pub subbanks: Vec<Subbank>,
+}
+
+/// 音階や表情毎のエイリアスの前後に付ける文字列。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Subbank {
+    /// 表情の名前。
+    pub color: String,
+    pub prefix: String,
+    pub suffix: String,
+    /// `C1-B7`や`C4`の形の音域。
+    pub tone_ranges: Vec<String>,
+}
+
+/// `C1-B7`や`C4`の形の音域を、含まれる音に展開する。
+fn parse_tone_range(range: &str) -> Option<Vec<MidiNote>> {
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (start.trim(), end.trim()),
+        None => (range.trim(), range.trim()),
+    };
+    let start = MidiNote::from_str(start).ok()?;
+    let end = MidiNote::from_str(end).ok()?;
+    Some((start.0..=end.0).map(MidiNote).collect())
+}
+
+impl CharacterYaml {
+    /// `Ongen::prefix_suffix_map`と同じ形の表を作る。最初のサブバンクの表情のものだけを使う。
+    pub fn prefix_suffix_map(&self) -> HashMap<String, (String, String)> {
+        let mut map = HashMap::new();
+        let Some(first) = self.subbanks.first() else {
+            return map;
+        };
+        for subbank in self
+            .subbanks
+            .iter()
+            .filter(|subbank| subbank.color == first.color)
+        {
+            for range in &subbank.tone_ranges {
+                let Some(notes) = parse_tone_range(range) else {
+                    continue;
+                };
+                for note in notes {
+                    map.entry(note.to_string())
+                        .or_insert_with(|| (subbank.prefix.clone(), subbank.suffix.clone()));
+                }
+            }
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subbank(color: &str, suffix: &str, tone_ranges: &[&str]) -> Subbank {
+        Subbank {
+            color: color.to_string(),
+            prefix: String::new(),
+            suffix: suffix.to_string(),
+            tone_ranges: tone_ranges.iter().map(|x| x.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_prefix_suffix_map() {
+        let character = CharacterYaml {
+            subbanks: vec![
+                subbank("", "_C4", &["C1-C4"]),
+                subbank("", "_G4", &["C#4-B7", "C4"]),
+                subbank("強", "_強", &["C1-B7"]),
+            ],
+            ..Default::default()
+        };
+        let map = character.prefix_suffix_map();
+
+        assert_eq!(
+            map.len(),
+            (MidiNote::from_str("B7").unwrap().0 - MidiNote::from_str("C1").unwrap().0 + 1)
+                as usize
+        );
+        assert_eq!(map["C1"], (String::new(), "_C4".to_string()));
+        assert_eq!(map["C4"], (String::new(), "_C4".to_string()));
+        assert_eq!(map["C#4"], (String::new(), "_G4".to_string()));
+        assert_eq!(map["B7"], (String::new(), "_G4".to_string()));
+    }
+}
@@ -0,0 +1,242 @@
+//! 合成時に時間で変化させるパラメーター（性別・張り・息・声の強さ）の曲線。
+//! スタイルの`formant_shift`などはフラグではなく、この曲線の基準の値として渡す。
+use crate::ongen_settings::StyleSettings;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use worldline::MS_PER_FRAME;
+
+/// 曲線の標準の値。
+static NEUTRAL: f32 = 0.5;
+// アクセント核に掛ける時の、前後のフェードの割合
+static ACCENT_FADE_RATE: f64 = 0.2;
+
+/// 変化させるパラメーター。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CurveKind {
+    /// 性別（g）
+    Gender,
+    /// 声の張り（Mt）
+    Tension,
+    /// 息の強さ（Mb）
+    Breathiness,
+    /// 声の強さ（Mv）
+    Voicing,
+}
+
+/// パラメーターを変化させる場所。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CurveTarget {
+    /// フレーズの最初の音。だんだん弱くなる。
+    PhraseStart,
+    /// フレーズの最後の音。だんだん強くなる。
+    PhraseEnd,
+    /// アクセント核のモーラ。
+    Accent,
+}
+
+impl CurveTarget {
+    /// 区間内の位置（0〜1）での掛かり具合。
+    fn weight(self, rate: f64) -> f64 {
+        match self {
+            Self::PhraseStart => 1.0 - rate,
+            Self::PhraseEnd => rate,
+            Self::Accent => (rate / ACCENT_FADE_RATE)
+                .min((1.0 - rate) / ACCENT_FADE_RATE)
+                .min(1.0),
+        }
+    }
+}
+
+/// スタイルの設定や、アクセント句毎に指定する曲線の変化。
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CurveRule {
+    pub curve: CurveKind,
+    pub target: CurveTarget,
+    /// -100〜100。
+    pub value: i8,
+}
+
+/// 曲線を変化させる区間。
+#[derive(Debug, Clone, PartialEq)]
+pub struct CurveSpan<'a> {
+    /// 開始位置（ms）。
+    pub start: f64,
+    /// 終了位置（ms）。
+    pub end: f64,
+    pub is_phrase_start: bool,
+    pub is_phrase_end: bool,
+    pub is_accent: bool,
+    /// この区間のアクセント句だけに掛けるルール。
+    pub rules: &'a [CurveRule],
+}
+
+impl CurveSpan<'_> {
+    fn matches(&self, target: CurveTarget) -> bool {
+        match target {
+            CurveTarget::PhraseStart => self.is_phrase_start,
+            CurveTarget::PhraseEnd => self.is_phrase_end,
+            CurveTarget::Accent => self.is_accent,
+        }
+    }
+}
+
+/// `PhraseSynth::set_curves`に渡す曲線。値はすべて`MS_PER_FRAME`毎で、f0以外は0〜1（0.5が標準）。
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderCurves {
+    pub f0: Vec<f32>,
+    pub gender: Vec<f32>,
+    pub tension: Vec<f32>,
+    pub breathiness: Vec<f32>,
+    pub voicing: Vec<f32>,
+}
+
+impl RenderCurves {
+    /// f0以外をスタイルの設定の値にした曲線を作る。
+    /// フラグの値（`g`と`Mt`は-100〜100、`Mb`と`Mv`は0〜100）を、0〜1の曲線の値に直す。
+    pub fn new(f0: Vec<f32>, style_settings: &StyleSettings) -> Self {
+        let constant = |value: f32| vec![value.clamp(0.0, 1.0); f0.len()];
+        Self {
+            gender: constant(NEUTRAL + style_settings.formant_shift as f32 / 200.0),
+            tension: constant(NEUTRAL + style_settings.tension as f32 / 200.0),
+            breathiness: constant(NEUTRAL + style_settings.breathiness as f32 / 200.0),
+            // Mvは100が標準
+            voicing: constant(NEUTRAL * style_settings.voicing as f32 / 100.0),
+            f0,
+        }
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        Self {
+            f0: self.f0[range.clone()].to_vec(),
+            gender: self.gender[range.clone()].to_vec(),
+            tension: self.tension[range.clone()].to_vec(),
+            breathiness: self.breathiness[range.clone()].to_vec(),
+            voicing: self.voicing[range].to_vec(),
+        }
+    }
+
+    fn curve_mut(&mut self, kind: CurveKind) -> &mut Vec<f32> {
+        match kind {
+            CurveKind::Gender => &mut self.gender,
+            CurveKind::Tension => &mut self.tension,
+            CurveKind::Breathiness => &mut self.breathiness,
+            CurveKind::Voicing => &mut self.voicing,
+        }
+    }
+
+    /// 区間に当てはまるルール（`rules`と、区間毎の`CurveSpan::rules`）を曲線に反映する。
+    pub fn apply_rules(&mut self, rules: &[CurveRule], spans: &[CurveSpan]) {
+        for span in spans {
+            let length = span.end - span.start;
+            if length <= 0.0 {
+                continue;
+            }
+            let start_frame = (span.start / MS_PER_FRAME).ceil().max(0.0) as usize;
+            let end_frame = (span.end / MS_PER_FRAME).floor() as usize;
+            for rule in rules
+                .iter()
+                .chain(span.rules)
+                .filter(|rule| span.matches(rule.target))
+            {
+                let amount = rule.value as f64 / 200.0;
+                let curve = self.curve_mut(rule.curve);
+                for (frame, value) in curve
+                    .iter_mut()
+                    .enumerate()
+                    .take(end_frame)
+                    .skip(start_frame)
+                {
+                    let rate = (frame as f64 * MS_PER_FRAME - span.start) / length;
+                    *value += (amount * rule.target.weight(rate)) as f32;
+                }
+            }
+        }
+        for curve in [
+            &mut self.gender,
+            &mut self.tension,
+            &mut self.breathiness,
+            &mut self.voicing,
+        ] {
+            for value in curve.iter_mut() {
+                *value = value.clamp(0.0, 1.0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_rules() {
+        let mut curves = RenderCurves::new(vec![440.0; 20], &StyleSettings::default());
+        curves.apply_rules(
+            &[
+                CurveRule {
+                    curve: CurveKind::Breathiness,
+                    target: CurveTarget::PhraseEnd,
+                    value: 100,
+                },
+                CurveRule {
+                    curve: CurveKind::Tension,
+                    target: CurveTarget::Accent,
+                    value: -100,
+                },
+            ],
+            &[
+                CurveSpan {
+                    start: 0.0,
+                    end: 100.0,
+                    is_phrase_start: true,
+                    is_phrase_end: false,
+                    is_accent: true,
+                    rules: &[],
+                },
+                CurveSpan {
+                    start: 100.0,
+                    end: 200.0,
+                    is_phrase_start: false,
+                    is_phrase_end: true,
+                    is_accent: false,
+                    rules: &[CurveRule {
+                        curve: CurveKind::Voicing,
+                        target: CurveTarget::PhraseEnd,
+                        value: -100,
+                    }],
+                },
+            ],
+        );
+
+        assert_eq!(curves.breathiness[0], 0.5);
+        assert_eq!(curves.breathiness[10], 0.5);
+        assert_eq!(curves.breathiness[15], 0.75);
+        assert_eq!(curves.tension[0], 0.5);
+        assert_eq!(curves.tension[5], 0.0);
+        assert_eq!(curves.tension[15], 0.5);
+        assert_eq!(curves.voicing[5], 0.5);
+        assert_eq!(curves.voicing[15], 0.25);
+        assert_eq!(curves.gender, vec![0.5; 20]);
+        assert_eq!(curves.f0, vec![440.0; 20]);
+    }
+
+    #[test]
+    fn test_style_baseline() {
+        let curves = RenderCurves::new(
+            vec![440.0; 2],
+            &StyleSettings {
+                formant_shift: -100,
+                tension: 50,
+                breathiness: 100,
+                voicing: 50,
+                ..Default::default()
+            },
+        );
+        assert_eq!(curves.gender, vec![0.0; 2]);
+        assert_eq!(curves.tension, vec![0.75; 2]);
+        assert_eq!(curves.breathiness, vec![1.0; 2]);
+        assert_eq!(curves.voicing, vec![0.25; 2]);
+    }
+}
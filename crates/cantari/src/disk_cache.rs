@@ -0,0 +1,53 @@
+//! `CACHE_DIR`に保存する、再起動しても残るキャッシュ。
+use crate::cache_dir::CACHE_DIR;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+use tracing::warn;
+use xxhash_rust::xxh3::xxh3_64;
+
+fn cache_path(kind: &str, key: &[u8]) -> PathBuf {
+    CACHE_DIR
+        .join(kind)
+        .join(format!("{:016x}.msgpack", xxh3_64(key)))
+}
+
+/// キャッシュを読む。無い場合や読めない場合はNoneを返す。
+pub async fn read_cache<T: DeserializeOwned>(kind: &str, key: &[u8]) -> Option<T> {
+    let data = tokio::fs::read(cache_path(kind, key)).await.ok()?;
+    match rmp_serde::from_slice(&data) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("Failed to decode {} cache: {}", kind, e);
+            None
+        }
+    }
+}
+
+/// キャッシュを書き込む。失敗しても警告を出すだけにする。
+pub async fn write_cache<T: Serialize>(kind: &str, key: &[u8], value: &T) {
+    let path = cache_path(kind, key);
+    let data = match rmp_serde::to_vec(value) {
+        Ok(data) => data,
+        Err(e) => {
+            warn!("Failed to encode {} cache: {}", kind, e);
+            return;
+        }
+    };
+    if let Err(e) = fs_err::tokio::create_dir_all(path.parent().unwrap()).await {
+        warn!("Failed to create {} cache dir: {}", kind, e);
+        return;
+    }
+    if let Err(e) = fs_err::tokio::write(&path, data).await {
+        warn!("Failed to write {} cache: {}", kind, e);
+    }
+}
+
+/// ファイルの更新日時（UNIX時間のナノ秒）。ファイルが無い場合はNoneを返す。
+pub async fn modified(path: &Path) -> Option<u64> {
+    let metadata = tokio::fs::metadata(path).await.ok()?;
+    let modified = metadata.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos() as u64)
+}
@@ -1,3 +1,4 @@
+use crate::kana_parser::{ParseKanaError, ParseKanaErrorDetail};
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
@@ -34,8 +35,24 @@ pub enum Error {
     AnalyzeFailed(#[source] anyhow::Error),
     #[error("音声合成中にエラーが発生しました")]
     SynthesisFailed(#[source] anyhow::Error),
+    #[error("音声合成がキャンセルされました")]
+    SynthesisCancelled,
+    #[error("音声ファイルを読み込めませんでした")]
+    ReadWaveFailed(#[source] anyhow::Error),
+    #[error("プロジェクトファイルを読み込めませんでした")]
+    ReadProjectFailed(#[source] anyhow::Error),
     #[error("話者が見つかりませんでした")]
     SpeakerNotFound,
+    #[error("プリセットが見つかりませんでした")]
+    PresetNotFound,
+    #[error("{0}")]
+    ParseKanaFailed(#[source] ParseKanaError),
+    #[error("モーフィングの割合は0から1の間で指定してください")]
+    InvalidMorphRate,
+    #[error("歌詞として使えない文字があります: {0}")]
+    InvalidLyric(String),
+    #[error("音素の並びが正しくありません: {0}")]
+    InvalidPhoneme(String),
 }
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -44,9 +61,22 @@ pub struct ErrorResponse {
     pub error: String,
 }
 
+// VOICEVOXのエディタが読むので、読み仮名のエラーはVOICEVOXと同じ形式で返す
+#[derive(Serialize)]
+pub struct ParseKanaErrorResponse {
+    pub detail: ParseKanaErrorDetail,
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         error!("Error: {}", self);
+        if let Self::ParseKanaFailed(e) = &self {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(&ParseKanaErrorResponse { detail: e.detail() }),
+            )
+                .into_response();
+        }
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(&ErrorResponse {
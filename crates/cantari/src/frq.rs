@@ -0,0 +1,105 @@
+//! UTAUの周波数表（`_wav.frq`）の作成。
+use crate::cache_dir::CACHE_DIR;
+use std::path::Path;
+use tracing::{info, warn};
+use worldline::F0Method;
+
+static FRQ_MAGIC: &[u8; 8] = b"FREQ0003";
+// 1フレームのサンプル数。UTAUの周波数表と同じにする
+static HOP_SIZE: usize = 256;
+
+/// F0と振幅から、周波数表のバイト列を作る。
+fn encode_frq(f0: &[f64], amplitude: &[f64]) -> Vec<u8> {
+    let voiced = f0.iter().copied().filter(|&x| x > 0.0).collect::<Vec<_>>();
+    let average_f0 = if voiced.is_empty() {
+        0.0
+    } else {
+        voiced.iter().sum::<f64>() / voiced.len() as f64
+    };
+
+    let mut frq = Vec::with_capacity(40 + f0.len() * 16);
+    frq.extend_from_slice(FRQ_MAGIC);
+    frq.extend_from_slice(&(HOP_SIZE as i32).to_le_bytes());
+    frq.extend_from_slice(&average_f0.to_le_bytes());
+    frq.extend_from_slice(&[0; 16]);
+    frq.extend_from_slice(&(f0.len() as i32).to_le_bytes());
+    for (f0, amplitude) in f0.iter().zip(amplitude) {
+        frq.extend_from_slice(&f0.to_le_bytes());
+        frq.extend_from_slice(&amplitude.to_le_bytes());
+    }
+    frq
+}
+
+/// 音声を解析して周波数表を作る。
+fn build_frq(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let frame_period = HOP_SIZE as f64 * 1000.0 / sample_rate as f64;
+    let f0 = worldline::f0(samples, sample_rate as i32, frame_period, F0Method::Dio);
+    let amplitude = (0..f0.len())
+        .map(|i| {
+            let start = (i * HOP_SIZE).min(samples.len());
+            let end = ((i + 1) * HOP_SIZE).min(samples.len());
+            let frame = &samples[start..end];
+            if frame.is_empty() {
+                return 0.0;
+            }
+            (frame.iter().map(|&x| (x as f64).powi(2)).sum::<f64>() / frame.len() as f64).sqrt()
+        })
+        .collect::<Vec<_>>();
+    encode_frq(&f0, &amplitude)
+}
+
+/// 周波数表の無い音声について、キャッシュから読むか、解析して作る。
+/// `hash`は音声ファイルのxxh3。`write_to_voicebank`がtrueなら`frq_path`にも書き込む。
+pub async fn load_or_build_frq(
+    frq_path: &Path,
+    hash: u64,
+    samples: &[f32],
+    sample_rate: u32,
+    write_to_voicebank: bool,
+) -> Option<Vec<u8>> {
+    let cache_path = CACHE_DIR.join("frq").join(format!("{:016x}_wav.frq", hash));
+    if let Ok(frq) = fs_err::tokio::read(&cache_path).await {
+        return Some(frq);
+    }
+
+    info!("Generating frq for {}", frq_path.display());
+    let samples = samples.to_vec();
+    let frq = match tokio::task::spawn_blocking(move || build_frq(&samples, sample_rate)).await {
+        Ok(frq) => frq,
+        Err(e) => {
+            warn!("Failed to generate frq: {}", e);
+            return None;
+        }
+    };
+
+    if let Err(e) = fs_err::tokio::create_dir_all(cache_path.parent().unwrap()).await {
+        warn!("Failed to create frq cache dir: {}", e);
+    } else if let Err(e) = fs_err::tokio::write(&cache_path, &frq).await {
+        warn!("Failed to write frq cache: {}", e);
+    }
+    if write_to_voicebank {
+        if let Err(e) = fs_err::tokio::write(frq_path, &frq).await {
+            warn!("Failed to write frq file: {}", e);
+        }
+    }
+
+    Some(frq)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_frq() {
+        let frq = encode_frq(&[0.0, 200.0, 400.0], &[0.0, 0.5, 0.25]);
+
+        assert_eq!(frq.len(), 40 + 3 * 16);
+        assert_eq!(&frq[0..8], b"FREQ0003");
+        assert_eq!(i32::from_le_bytes(frq[8..12].try_into().unwrap()), 256);
+        assert_eq!(f64::from_le_bytes(frq[12..20].try_into().unwrap()), 300.0);
+        assert_eq!(i32::from_le_bytes(frq[36..40].try_into().unwrap()), 3);
+        assert_eq!(f64::from_le_bytes(frq[56..64].try_into().unwrap()), 200.0);
+        assert_eq!(f64::from_le_bytes(frq[64..72].try_into().unwrap()), 0.5);
+    }
+}
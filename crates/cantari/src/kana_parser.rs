@@ -0,0 +1,240 @@
+//! VOICEVOXのAquesTalk風記法（`コンニチワ'/`など）のパーサー。
+//! 実際のAudioQueryの生成はVoicevox Coreに任せ、ここでは検証とエラーの生成だけを行う。
+// https://github.com/VOICEVOX/voicevox_engine/blob/master/voicevox_engine/tts_pipeline/kana_converter.py
+use crate::mora_table::MORA_LIST;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+static LOOP_LIMIT: usize = 300;
+static PAUSE_DELIMITER: char = '、';
+static NOPAUSE_DELIMITER: char = '/';
+static UNVOICE_SYMBOL: char = '_';
+static ACCENT_SYMBOL: char = '\'';
+static WIDE_INTERROGATION_MARK: char = '？';
+
+/// 読み仮名として使える文字列。無声化（`_ア`など）も含む。
+static KANA_TEXTS: Lazy<HashSet<String>> = Lazy::new(|| {
+    let mut texts = HashSet::new();
+    for mora in MORA_LIST {
+        texts.insert(mora.text.to_string());
+        if ["a", "i", "u", "e", "o"].contains(&mora.vowel) {
+            texts.insert(format!("{}{}", UNVOICE_SYMBOL, mora.text));
+        }
+    }
+    texts
+});
+
+/// 読み仮名のパースに失敗した理由。
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ParseKanaError {
+    #[error("判別できない読み仮名があります: {0}")]
+    UnknownText(String),
+    #[error("句頭にアクセントは置けません: {0}")]
+    AccentTop(String),
+    #[error("1つのアクセント句に二つ以上のアクセントは置けません: {0}")]
+    AccentTwice(String),
+    #[error("アクセントを指定していないアクセント句があります: {0}")]
+    AccentNotFound(String),
+    #[error("{0}番目のアクセント句が空白です")]
+    EmptyPhrase(usize),
+    #[error("アクセント句末以外に「？」は置けません: {0}")]
+    InterrogationMarkNotAtEnd(String),
+    #[error("処理時に無限ループになってしまいました...バグ報告をお願いします。")]
+    InfiniteLoop,
+}
+
+/// VOICEVOXと同じ形式のエラー。
+#[derive(Debug, Serialize)]
+pub struct ParseKanaErrorDetail {
+    pub text: String,
+    pub error_name: String,
+    pub error_args: HashMap<String, String>,
+}
+
+impl ParseKanaError {
+    pub fn detail(&self) -> ParseKanaErrorDetail {
+        let (error_name, error_args) = match self {
+            Self::UnknownText(text) => ("UNKNOWN_TEXT", vec![("text", text.clone())]),
+            Self::AccentTop(text) => ("ACCENT_TOP", vec![("text", text.clone())]),
+            Self::AccentTwice(text) => ("ACCENT_TWICE", vec![("text", text.clone())]),
+            Self::AccentNotFound(text) => ("ACCENT_NOTFOUND", vec![("text", text.clone())]),
+            Self::EmptyPhrase(position) => {
+                ("EMPTY_PHRASE", vec![("position", position.to_string())])
+            }
+            Self::InterrogationMarkNotAtEnd(text) => (
+                "INTERROGATION_MARK_NOT_AT_END",
+                vec![("text", text.clone())],
+            ),
+            Self::InfiniteLoop => ("INFINITE_LOOP", vec![]),
+        };
+
+        ParseKanaErrorDetail {
+            text: self.to_string(),
+            error_name: error_name.to_string(),
+            error_args: error_args
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        }
+    }
+}
+
+fn parse_accent_phrase(phrase: &[char]) -> Result<(Vec<String>, usize), ParseKanaError> {
+    let phrase_text = phrase.iter().collect::<String>();
+    let mut accent = None;
+    let mut moras: Vec<String> = vec![];
+    let mut base_index = 0;
+    let mut loop_count = 0;
+    while base_index < phrase.len() {
+        loop_count += 1;
+        if loop_count > LOOP_LIMIT {
+            return Err(ParseKanaError::InfiniteLoop);
+        }
+        if phrase[base_index] == ACCENT_SYMBOL {
+            if moras.is_empty() {
+                return Err(ParseKanaError::AccentTop(phrase_text));
+            }
+            if accent.is_some() {
+                return Err(ParseKanaError::AccentTwice(phrase_text));
+            }
+            accent = Some(moras.len());
+            base_index += 1;
+            continue;
+        }
+
+        // 一番長く一致するモーラを探す
+        let mut stack = String::new();
+        let mut matched = None;
+        for &c in phrase[base_index..]
+            .iter()
+            .take_while(|&&c| c != ACCENT_SYMBOL)
+        {
+            stack.push(c);
+            if KANA_TEXTS.contains(&stack) {
+                matched = Some(stack.clone());
+            }
+        }
+        let Some(matched) = matched else {
+            return Err(ParseKanaError::UnknownText(stack));
+        };
+        base_index += matched.chars().count();
+        moras.push(matched);
+    }
+
+    let accent = accent.ok_or(ParseKanaError::AccentNotFound(phrase_text))?;
+    Ok((moras, accent))
+}
+
+/// AquesTalk風記法の読み仮名を検証する。
+pub fn validate_kana(text: &str) -> Result<(), ParseKanaError> {
+    let chars = text.chars().collect::<Vec<_>>();
+    if chars.is_empty() {
+        return Err(ParseKanaError::EmptyPhrase(1));
+    }
+
+    let mut phrase_count = 0;
+    let mut phrase_start = 0;
+    for i in 0..=chars.len() {
+        let delimiter = chars.get(i).copied();
+        if delimiter.is_some_and(|c| c != PAUSE_DELIMITER && c != NOPAUSE_DELIMITER) {
+            continue;
+        }
+
+        let phrase = &chars[phrase_start..i];
+        if phrase.is_empty() {
+            return Err(ParseKanaError::EmptyPhrase(phrase_count + 1));
+        }
+        phrase_start = i + 1;
+
+        let phrase = if phrase.contains(&WIDE_INTERROGATION_MARK) {
+            if phrase[..phrase.len() - 1].contains(&WIDE_INTERROGATION_MARK) {
+                return Err(ParseKanaError::InterrogationMarkNotAtEnd(
+                    phrase.iter().collect(),
+                ));
+            }
+            &phrase[..phrase.len() - 1]
+        } else {
+            phrase
+        };
+
+        parse_accent_phrase(phrase)?;
+        phrase_count += 1;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_accent_phrase() {
+        let chars = |text: &str| text.chars().collect::<Vec<_>>();
+        assert_eq!(
+            parse_accent_phrase(&chars("コンニチワ'")),
+            Ok((
+                vec!["コ", "ン", "ニ", "チ", "ワ"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+                5
+            ))
+        );
+        assert_eq!(
+            parse_accent_phrase(&chars("_キョ'ウワ")),
+            Ok((
+                vec!["_キョ", "ウ", "ワ"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+                1
+            ))
+        );
+    }
+
+    #[test]
+    fn test_validate_kana() {
+        assert_eq!(
+            validate_kana("コンニチワ'、_キョ'ーワ/イイテ'ンキデスネ？"),
+            Err(ParseKanaError::UnknownText("ーワ".to_string()))
+        );
+        assert_eq!(
+            validate_kana("コンニチワ'、_キョ'ウワ/イイテ'ンキデスネ？"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_validate_kana_errors() {
+        assert_eq!(validate_kana(""), Err(ParseKanaError::EmptyPhrase(1)));
+        assert_eq!(
+            validate_kana("ア'//イ'"),
+            Err(ParseKanaError::EmptyPhrase(2))
+        );
+        assert_eq!(
+            validate_kana("'アイ"),
+            Err(ParseKanaError::AccentTop("'アイ".to_string()))
+        );
+        assert_eq!(
+            validate_kana("ア'イ'"),
+            Err(ParseKanaError::AccentTwice("ア'イ'".to_string()))
+        );
+        assert_eq!(
+            validate_kana("アイ"),
+            Err(ParseKanaError::AccentNotFound("アイ".to_string()))
+        );
+        assert_eq!(
+            validate_kana("ア？イ'"),
+            Err(ParseKanaError::InterrogationMarkNotAtEnd(
+                "ア？イ'".to_string()
+            ))
+        );
+        assert_eq!(
+            validate_kana("ア'x").unwrap_err().detail().error_name,
+            "UNKNOWN_TEXT"
+        );
+    }
+}
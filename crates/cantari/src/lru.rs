@@ -0,0 +1,167 @@
+//! 大きさの合計で上限を決めるLRUキャッシュ。
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+#[derive(Debug)]
+struct LruEntry<V> {
+    value: V,
+    size: usize,
+    /// 最後に使った順番。
+    tick: u64,
+}
+
+/// 診断用の統計。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LruStats {
+    pub entries: usize,
+    /// 保持している大きさの合計（バイト）。
+    pub size: usize,
+    pub limit: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+/// 値の大きさの合計が`limit`を超えたら、最も長く使われていないものから捨てる。
+#[derive(Debug)]
+pub struct SizedLru<K, V> {
+    entries: HashMap<K, LruEntry<V>>,
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    size: usize,
+    limit: usize,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> SizedLru<K, V> {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            size: 0,
+            limit,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        let tick = self.next_tick();
+        let Some(entry) = self.entries.get_mut(key) else {
+            self.misses += 1;
+            return None;
+        };
+        self.hits += 1;
+        let key = self.order.remove(&entry.tick).unwrap();
+        entry.tick = tick;
+        self.order.insert(tick, key);
+        Some(entry.value.clone())
+    }
+
+    /// 値を追加する。上限より大きい値は追加しない。
+    pub fn insert(&mut self, key: K, value: V, size: usize) {
+        self.remove(&key);
+        if size > self.limit {
+            return;
+        }
+        let tick = self.next_tick();
+        self.order.insert(tick, key.clone());
+        self.entries.insert(key, LruEntry { value, size, tick });
+        self.size += size;
+        self.evict();
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.tick);
+            self.size -= entry.size;
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.limit {
+            let Some((_, key)) = self.order.pop_first() else {
+                break;
+            };
+            let entry = self.entries.remove(&key).unwrap();
+            self.size -= entry.size;
+            self.evictions += 1;
+        }
+    }
+
+    /// `f`がfalseを返したものを捨てる。
+    pub fn retain(&mut self, mut f: impl FnMut(&K) -> bool) {
+        let removed = self
+            .entries
+            .keys()
+            .filter(|key| !f(key))
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in removed {
+            self.remove(&key);
+        }
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.evict();
+    }
+
+    pub fn stats(&self) -> LruStats {
+        LruStats {
+            entries: self.entries.len(),
+            size: self.size,
+            limit: self.limit,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sized_lru() {
+        let mut lru = SizedLru::new(10);
+        lru.insert("a", 1, 4);
+        lru.insert("b", 2, 4);
+        assert_eq!(lru.get(&"a"), Some(1));
+        // bが最も長く使われていないので捨てられる
+        lru.insert("c", 3, 4);
+        assert_eq!(lru.get(&"b"), None);
+        assert_eq!(lru.get(&"a"), Some(1));
+        assert_eq!(lru.get(&"c"), Some(3));
+        // 上限より大きいものは追加されず、他のものも捨てられない
+        lru.insert("d", 4, 11);
+        assert_eq!(lru.get(&"d"), None);
+
+        lru.set_limit(4);
+        assert_eq!(
+            lru.stats(),
+            LruStats {
+                entries: 1,
+                size: 4,
+                limit: 4,
+                hits: 3,
+                misses: 2,
+                evictions: 2,
+            }
+        );
+        assert_eq!(lru.get(&"c"), Some(3));
+    }
+}
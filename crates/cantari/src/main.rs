@@ -1,12 +1,27 @@
+mod alias_fallback;
+mod alias_script;
+mod cache_dir;
+mod character_yaml;
+mod curves;
+mod disk_cache;
 mod error;
+mod frq;
+mod kana_parser;
+mod lru;
 mod math;
 mod model;
+mod mora_table;
 mod ongen;
 mod ongen_settings;
 mod oto;
+mod preset;
 mod routes;
 mod settings;
 mod tempdir;
+mod text_encoding;
+mod ust;
+mod voicebank_type;
+mod watcher;
 
 use crate::{
     routes::{audio_query::get_or_initialize_synthesizer, user_dict::get_or_initialize_user_dict},
@@ -19,9 +34,13 @@ use axum::{
     routing::{delete, get, post, put},
     Router,
 };
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use ongen::ONGEN;
-use std::net::SocketAddr;
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, Arc},
+};
 use tower_http::{cors::CorsLayer, trace};
 use tracing::{info, Level};
 
@@ -40,6 +59,23 @@ struct Cli {
     /// ホスト名。
     #[clap(long, default_value = "127.0.0.1")]
     host: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// UTAUの.ustかOpenUtauの.ustxを合成し、WAVに書き出す。
+    Render {
+        /// 読み込むプロジェクトファイル。
+        input: PathBuf,
+        /// 書き出すWAVファイル。
+        #[clap(short, long)]
+        output: PathBuf,
+        /// 使うスタイルのID。
+        #[clap(short, long)]
+        speaker: u32,
+    },
 }
 
 #[tokio::main]
@@ -56,7 +92,14 @@ async fn main() -> Result<()> {
         .with_ansi(cfg!(debug_assertions))
         .init();
 
-    let result = main_impl(args).await;
+    let result = match args.command {
+        Some(Command::Render {
+            ref input,
+            ref output,
+            speaker,
+        }) => render_impl(input, output, speaker).await,
+        None => main_impl(args).await,
+    };
 
     info!("Shutting down...");
 
@@ -77,6 +120,8 @@ async fn main_impl(args: Cli) -> Result<()> {
             )
             .route("/speakers", get(routes::speakers::get_speakers))
             .route("/speaker_info", get(routes::speakers::get_speaker_info))
+            .route("/singers", get(routes::speakers::get_singers))
+            .route("/singer_info", get(routes::speakers::get_singer_info))
             .route(
                 "/speaker_resources/icons/:uuid/:index",
                 get(routes::speakers::get_icon),
@@ -97,6 +142,36 @@ async fn main_impl(args: Cli) -> Result<()> {
             .route("/mora_pitch", post(routes::audio_query::post_mora_pitch))
             .route("/mora_length", post(routes::audio_query::post_mora_length))
             .route("/synthesis", post(routes::synthesis::post_synthesis))
+            .route(
+                "/cancellable_synthesis",
+                post(routes::synthesis::post_cancellable_synthesis),
+            )
+            .route(
+                "/multi_synthesis",
+                post(routes::synthesis::post_multi_synthesis),
+            )
+            .route(
+                "/sing_frame_audio_query",
+                post(routes::sing::post_sing_frame_audio_query),
+            )
+            .route("/frame_synthesis", post(routes::sing::post_frame_synthesis))
+            .route("/song_synthesis", post(routes::song::post_song_synthesis))
+            .route(
+                "/morphable_targets",
+                post(routes::morphing::post_morphable_targets),
+            )
+            .route(
+                "/synthesis_morphing",
+                post(routes::morphing::post_synthesis_morphing),
+            )
+            .route(
+                "/connect_waves",
+                post(routes::connect_waves::post_connect_waves),
+            )
+            .route("/presets", get(routes::presets::get_presets))
+            .route("/add_preset", post(routes::presets::post_add_preset))
+            .route("/update_preset", post(routes::presets::post_update_preset))
+            .route("/delete_preset", post(routes::presets::post_delete_preset))
             .route("/user_dict", get(routes::user_dict::get_user_dict))
             .route(
                 "/import_user_dict",
@@ -115,6 +190,14 @@ async fn main_impl(args: Cli) -> Result<()> {
                 put(routes::user_dict::put_user_dict_word),
             )
             .route("/audio_query", post(routes::audio_query::post_audio_query))
+            .route(
+                "/validate_kana",
+                post(routes::audio_query::post_validate_kana),
+            )
+            .route(
+                "/audio_query_from_preset",
+                post(routes::audio_query::post_audio_query_from_preset),
+            )
             .route(
                 "/accent_phrases",
                 post(routes::audio_query::post_accent_phrases),
@@ -124,6 +207,14 @@ async fn main_impl(args: Cli) -> Result<()> {
                 get(routes::settings::get_settings).put(routes::settings::put_settings),
             )
             .route("/icons/:uuid", get(routes::settings::get_icon))
+            .route(
+                "/fallback_report/:uuid",
+                get(routes::settings::get_fallback_report),
+            )
+            .route(
+                "/sample_cache_stats",
+                get(routes::settings::get_sample_cache_stats),
+            )
             .layer(CorsLayer::permissive())
             .layer(
                 trace::TraceLayer::new_for_http()
@@ -150,6 +241,7 @@ async fn main_impl(args: Cli) -> Result<()> {
             info!("- {} ({}, {})", ongen.name(), ongen.uuid, ongen.id());
         }
     }
+    tokio::spawn(watcher::watch_ongens());
 
     if TEMPDIR.exists() {
         tokio::fs::remove_dir_all(TEMPDIR.as_path()).await?;
@@ -183,6 +275,22 @@ async fn main_impl(args: Cli) -> Result<()> {
     Ok(())
 }
 
+async fn render_impl(input: &Path, output: &Path, speaker: u32) -> Result<()> {
+    setup_ongen().await;
+
+    let song = ust::read_project(&fs_err::tokio::read(input).await?)?;
+    info!("Loaded {} notes from {}", song.notes.len(), input.display());
+    let wav = routes::song::render_song(&song, speaker, Arc::new(AtomicBool::new(false))).await?;
+    fs_err::tokio::write(
+        output,
+        routes::synthesis::write_float_wav(&wav, worldline::SAMPLE_RATE, false),
+    )
+    .await?;
+    info!("Wrote {}", output.display());
+
+    Ok(())
+}
+
 async fn get_index() -> impl IntoResponse {
     Redirect::permanent("/settings")
 }
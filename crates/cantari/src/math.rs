@@ -1,6 +1,6 @@
 use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MidiNote(pub u8);
 
 impl MidiNote {
@@ -12,13 +12,35 @@ impl MidiNote {
         self.0
     }
 
+    pub fn to_frequency(self) -> f32 {
+        440.0 * 2.0_f32.powf((self.0 as f32 - 69.0) / 12.0)
+    }
+}
+
+/// 一番近いMidiNoteと、そこからのずれ（セント）で表した音高。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pitch {
+    pub note: MidiNote,
+    pub cents: f32,
+}
+
+impl Pitch {
     pub fn from_frequency(frequency: f32) -> Self {
         let midi_number = 69.0 + 12.0 * (frequency / 440.0).log2();
-        Self(midi_number as u8)
+        let rounded = midi_number.round();
+        Self {
+            note: MidiNote(rounded.clamp(0.0, 127.0) as u8),
+            cents: (midi_number - rounded) * 100.0,
+        }
     }
 
-    pub fn to_frequency(self) -> f32 {
-        440.0 * 2.0_f32.powf((self.0 as f32 - 69.0) / 12.0)
+    /// 半音単位でずらす。ずらした後の音は`min`〜`max`に収める。
+    pub fn shift(self, semitones: i32, min: MidiNote, max: MidiNote) -> Self {
+        let note = (self.note.0 as i32 + semitones).clamp(min.0 as i32, max.0 as i32) as u8;
+        Self {
+            note: MidiNote(note),
+            cents: self.cents,
+        }
     }
 }
 
@@ -88,6 +110,29 @@ pub fn smooth(base: &[f32], window_size: usize) -> Vec<f32> {
     smoothed
 }
 
+/// 線形補間で`values[index]`を求める。範囲外の場合は端の値を返す。
+pub fn interpolate(values: &[f32], index: f64) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let index = index.clamp(0.0, (values.len() - 1) as f64);
+    let floor = index.floor() as usize;
+    let ceil = index.ceil() as usize;
+    let t = (index - floor as f64) as f32;
+    values[floor] * (1.0 - t) + values[ceil] * t
+}
+
+/// 2つの波形を`rate`の割合で混ぜる。短い方は無音で埋める。
+pub fn mix(base: &[f32], target: &[f32], rate: f32) -> Vec<f32> {
+    (0..base.len().max(target.len()))
+        .map(|i| {
+            let base = base.get(i).copied().unwrap_or(0.0);
+            let target = target.get(i).copied().unwrap_or(0.0);
+            base * (1.0 - rate) + target * rate
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -97,9 +142,6 @@ mod tests {
         let midi_note = MidiNote::from_midi_number(69);
         assert_eq!(midi_note.to_frequency(), 440.0);
 
-        let midi_note = MidiNote::from_frequency(440.0);
-        assert_eq!(midi_note.to_midi_number(), 69);
-
         let midi_note = MidiNote::from_str("A4").unwrap();
         assert_eq!(midi_note.to_midi_number(), 69);
 
@@ -112,4 +154,46 @@ mod tests {
         let midi_note = MidiNote::from_str("C5").unwrap();
         assert_eq!(midi_note.to_midi_number(), 72);
     }
+
+    #[test]
+    fn test_pitch() {
+        let pitch = Pitch::from_frequency(440.0);
+        assert_eq!(pitch.note.to_midi_number(), 69);
+        assert!(pitch.cents.abs() < 0.01);
+
+        // A4から+40セント
+        let pitch = Pitch::from_frequency(440.0 * 2.0_f32.powf(40.0 / 1200.0));
+        assert_eq!(pitch.note.to_midi_number(), 69);
+        assert!((pitch.cents - 40.0).abs() < 0.01);
+
+        // A4から+60セントは、A#4から-40セントになる
+        let pitch = Pitch::from_frequency(440.0 * 2.0_f32.powf(60.0 / 1200.0));
+        assert_eq!(pitch.note.to_midi_number(), 70);
+        assert!((pitch.cents + 40.0).abs() < 0.01);
+
+        let pitch = pitch.shift(-12, MidiNote(24), MidiNote(107));
+        assert_eq!(pitch.note.to_midi_number(), 58);
+        assert!((pitch.cents + 40.0).abs() < 0.01);
+        let pitch = pitch.shift(-100, MidiNote(24), MidiNote(107));
+        assert_eq!(pitch.note.to_midi_number(), 24);
+    }
+
+    #[test]
+    fn test_mix() {
+        assert_eq!(
+            mix(&[1.0, 1.0], &[0.0, 0.0, 1.0], 0.25),
+            vec![0.75, 0.75, 0.25]
+        );
+        assert_eq!(mix(&[1.0], &[0.0], 0.0), vec![1.0]);
+        assert_eq!(mix(&[1.0], &[0.0], 1.0), vec![0.0]);
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let values = [0.0, 10.0, 20.0];
+        assert_eq!(interpolate(&values, 0.5), 5.0);
+        assert_eq!(interpolate(&values, 2.0), 20.0);
+        assert_eq!(interpolate(&values, -1.0), 0.0);
+        assert_eq!(interpolate(&values, 10.0), 20.0);
+    }
 }
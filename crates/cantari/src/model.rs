@@ -1,5 +1,17 @@
+use crate::curves::CurveRule;
 use serde::{Deserialize, Serialize};
 
+// 疑問文の語尾上げ：https://github.com/VOICEVOX/voicevox_engine/blob/master/voicevox_engine/tts_pipeline/tts_engine.py
+pub static UPSPEAK_MORA_TEXT: &str = "ー";
+static UPSPEAK_LENGTH: f32 = 0.15;
+static UPSPEAK_PITCH_ADD: f32 = 0.3;
+static UPSPEAK_PITCH_MAX: f32 = 6.5;
+
+/// 長音（語尾上げのために足したものを含む）のモーラかどうか。
+pub fn is_long_vowel_mora(mora: &MoraModel) -> bool {
+    mora.text == UPSPEAK_MORA_TEXT && mora.consonant.is_none()
+}
+
 // https://github.com/VOICEVOX/voicevox_core/blob/main/crates/voicevox_core/src/engine/model.rs
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct MoraModel {
@@ -26,6 +38,10 @@ pub struct AccentPhraseModel {
 
     #[serde(default)]
     pub is_interrogative: bool,
+
+    /// このアクセント句だけに掛ける、声質の曲線の変化。スタイルの設定のものに足される。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub curve_rules: Vec<CurveRule>,
 }
 
 impl AccentPhraseModel {
@@ -55,6 +71,33 @@ impl AccentPhraseModel {
             }
         }
 
+        modified
+    }
+    /// 疑問文なら、語尾を上げるためのモーラを末尾に足す。
+    pub fn apply_interrogative_upspeak(&self) -> AccentPhraseModel {
+        let mut modified = self.clone();
+        if !self.is_interrogative {
+            return modified;
+        }
+        let Some(last_mora) = self.moras.last() else {
+            return modified;
+        };
+        // 無声化している時と、既に伸ばしてある時は何もしない
+        if last_mora.pitch == 0.0 || is_long_vowel_mora(last_mora) {
+            return modified;
+        }
+        modified.moras.push(MoraModel {
+            text: UPSPEAK_MORA_TEXT.to_string(),
+            consonant: None,
+            consonant_length: None,
+            vowel: last_mora.vowel.clone(),
+            vowel_length: UPSPEAK_LENGTH,
+            // 元から上限より高い時は、下げずにそのままにする
+            pitch: (last_mora.pitch + UPSPEAK_PITCH_ADD)
+                .min(UPSPEAK_PITCH_MAX)
+                .max(last_mora.pitch),
+        });
+
         modified
     }
 }
@@ -82,6 +125,14 @@ pub struct AudioQueryModel {
     pub kana: Option<String>,
 }
 impl AudioQueryModel {
+    pub fn apply_interrogative_upspeak(&self) -> AudioQueryModel {
+        let mut modified = self.clone();
+        if let Some(last_accent_phrase) = modified.accent_phrases.last_mut() {
+            *last_accent_phrase = last_accent_phrase.apply_interrogative_upspeak();
+        }
+
+        modified
+    }
     pub fn apply_speed_scale(&self, speed_scale: f32) -> AudioQueryModel {
         let mut modified = self.clone();
         modified.accent_phrases = self
@@ -163,3 +214,89 @@ impl From<&AccentPhraseModel> for voicevox_core::AccentPhraseModel {
         serde_json::from_str(&json).unwrap()
     }
 }
+
+// https://github.com/VOICEVOX/voicevox_engine/blob/master/voicevox_engine/model.py
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct NoteModel {
+    #[serde(default)]
+    pub id: Option<String>,
+
+    /// 休符の場合はNone
+    pub key: Option<i32>,
+
+    pub frame_length: usize,
+
+    pub lyric: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ScoreModel {
+    pub notes: Vec<NoteModel>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct FramePhonemeModel {
+    pub phoneme: String,
+
+    pub frame_length: usize,
+
+    #[serde(default)]
+    pub note_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameAudioQueryModel {
+    pub f0: Vec<f32>,
+
+    pub volume: Vec<f32>,
+
+    pub phonemes: Vec<FramePhonemeModel>,
+
+    pub volume_scale: f32,
+
+    pub output_sampling_rate: serde_json::Number,
+
+    pub output_stereo: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accent_phrase(pitch: f32) -> AccentPhraseModel {
+        AccentPhraseModel {
+            moras: vec![MoraModel {
+                text: "カ".to_string(),
+                consonant: Some("k".to_string()),
+                consonant_length: Some(0.05),
+                vowel: "a".to_string(),
+                vowel_length: 0.1,
+                pitch,
+            }],
+            accent: 1,
+            pause_mora: None,
+            is_interrogative: true,
+            curve_rules: vec![],
+        }
+    }
+
+    #[test]
+    fn test_apply_interrogative_upspeak() {
+        let modified = accent_phrase(5.5).apply_interrogative_upspeak();
+        assert_eq!(modified.moras.len(), 2);
+        assert_eq!(modified.moras[1].text, UPSPEAK_MORA_TEXT);
+        assert_eq!(modified.moras[1].vowel, "a");
+        assert_eq!(modified.moras[1].pitch, 5.5 + UPSPEAK_PITCH_ADD);
+
+        let modified = accent_phrase(6.4).apply_interrogative_upspeak();
+        assert_eq!(modified.moras[1].pitch, UPSPEAK_PITCH_MAX);
+
+        // 上限より高い時は下げない
+        let modified = accent_phrase(7.0).apply_interrogative_upspeak();
+        assert_eq!(modified.moras[1].pitch, 7.0);
+
+        let modified = accent_phrase(0.0).apply_interrogative_upspeak();
+        assert_eq!(modified.moras.len(), 1);
+    }
+}
@@ -0,0 +1,77 @@
+//! VOICEVOXのモーラ（カナと音素の対応）の一覧。
+// https://github.com/VOICEVOX/voicevox_engine/blob/master/voicevox_engine/tts_pipeline/mora_mapping.py
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mora {
+    /// カタカナ
+    pub text: &'static str,
+    pub consonant: Option<&'static str>,
+    pub vowel: &'static str,
+}
+
+const fn m(text: &'static str, consonant: Option<&'static str>, vowel: &'static str) -> Mora {
+    Mora {
+        text,
+        consonant,
+        vowel,
+    }
+}
+
+// 同じ音素の組み合わせがある場合は、先にある方を優先する
+#[rustfmt::skip]
+pub static MORA_LIST: &[Mora] = &[
+    m("ヴォ", Some("v"), "o"), m("ヴェ", Some("v"), "e"), m("ヴィ", Some("v"), "i"), m("ヴァ", Some("v"), "a"), m("ヴ", Some("v"), "u"),
+    m("ン", None, "N"),
+    m("ワ", Some("w"), "a"), m("ロ", Some("r"), "o"), m("レ", Some("r"), "e"), m("ル", Some("r"), "u"),
+    m("リャ", Some("ry"), "a"), m("リュ", Some("ry"), "u"), m("リョ", Some("ry"), "o"), m("リェ", Some("ry"), "e"), m("リ", Some("r"), "i"), m("ラ", Some("r"), "a"),
+    m("ヨ", Some("y"), "o"), m("ユ", Some("y"), "u"), m("ヤ", Some("y"), "a"),
+    m("モ", Some("m"), "o"), m("メ", Some("m"), "e"), m("ム", Some("m"), "u"),
+    m("ミャ", Some("my"), "a"), m("ミュ", Some("my"), "u"), m("ミョ", Some("my"), "o"), m("ミェ", Some("my"), "e"), m("ミ", Some("m"), "i"), m("マ", Some("m"), "a"),
+    m("ポ", Some("p"), "o"), m("ボ", Some("b"), "o"), m("ホ", Some("h"), "o"),
+    m("ドゥ", Some("d"), "u"), m("トゥ", Some("t"), "u"),
+    m("ペ", Some("p"), "e"), m("ベ", Some("b"), "e"), m("ヘ", Some("h"), "e"), m("プ", Some("p"), "u"), m("ブ", Some("b"), "u"),
+    m("フォ", Some("f"), "o"), m("フェ", Some("f"), "e"), m("フィ", Some("f"), "i"), m("ファ", Some("f"), "a"), m("フ", Some("f"), "u"),
+    m("ピャ", Some("py"), "a"), m("ピュ", Some("py"), "u"), m("ピョ", Some("py"), "o"), m("ピェ", Some("py"), "e"), m("ピ", Some("p"), "i"),
+    m("ビャ", Some("by"), "a"), m("ビュ", Some("by"), "u"), m("ビョ", Some("by"), "o"), m("ビェ", Some("by"), "e"), m("ビ", Some("b"), "i"),
+    m("ヒャ", Some("hy"), "a"), m("ヒュ", Some("hy"), "u"), m("ヒョ", Some("hy"), "o"), m("ヒェ", Some("hy"), "e"), m("ヒ", Some("h"), "i"),
+    m("パ", Some("p"), "a"), m("バ", Some("b"), "a"), m("ハ", Some("h"), "a"),
+    m("ノ", Some("n"), "o"), m("ネ", Some("n"), "e"), m("ヌ", Some("n"), "u"),
+    m("ニャ", Some("ny"), "a"), m("ニュ", Some("ny"), "u"), m("ニョ", Some("ny"), "o"), m("ニェ", Some("ny"), "e"), m("ニ", Some("n"), "i"), m("ナ", Some("n"), "a"),
+    m("ド", Some("d"), "o"), m("ト", Some("t"), "o"),
+    m("デャ", Some("dy"), "a"), m("デュ", Some("dy"), "u"), m("デョ", Some("dy"), "o"), m("ディ", Some("d"), "i"), m("デ", Some("d"), "e"),
+    m("テャ", Some("ty"), "a"), m("テュ", Some("ty"), "u"), m("テョ", Some("ty"), "o"), m("ティ", Some("t"), "i"), m("テ", Some("t"), "e"),
+    m("ツォ", Some("ts"), "o"), m("ツェ", Some("ts"), "e"), m("ツィ", Some("ts"), "i"), m("ツァ", Some("ts"), "a"), m("ツ", Some("ts"), "u"),
+    m("ッ", None, "cl"),
+    m("チャ", Some("ch"), "a"), m("チュ", Some("ch"), "u"), m("チョ", Some("ch"), "o"), m("チェ", Some("ch"), "e"), m("チ", Some("ch"), "i"),
+    m("ダ", Some("d"), "a"), m("タ", Some("t"), "a"),
+    m("ゾ", Some("z"), "o"), m("ソ", Some("s"), "o"), m("ゼ", Some("z"), "e"), m("セ", Some("s"), "e"),
+    m("ズィ", Some("z"), "i"), m("ズ", Some("z"), "u"), m("スィ", Some("s"), "i"), m("ス", Some("s"), "u"),
+    m("ジャ", Some("j"), "a"), m("ジュ", Some("j"), "u"), m("ジョ", Some("j"), "o"), m("ジェ", Some("j"), "e"), m("ジ", Some("j"), "i"),
+    m("シャ", Some("sh"), "a"), m("シュ", Some("sh"), "u"), m("ショ", Some("sh"), "o"), m("シェ", Some("sh"), "e"), m("シ", Some("sh"), "i"),
+    m("ザ", Some("z"), "a"), m("サ", Some("s"), "a"),
+    m("ゴ", Some("g"), "o"), m("コ", Some("k"), "o"), m("ゲ", Some("g"), "e"), m("ケ", Some("k"), "e"),
+    m("グヮ", Some("gw"), "a"), m("グ", Some("g"), "u"), m("クヮ", Some("kw"), "a"), m("ク", Some("k"), "u"),
+    m("ギャ", Some("gy"), "a"), m("ギュ", Some("gy"), "u"), m("ギョ", Some("gy"), "o"), m("ギェ", Some("gy"), "e"), m("ギ", Some("g"), "i"),
+    m("キャ", Some("ky"), "a"), m("キュ", Some("ky"), "u"), m("キョ", Some("ky"), "o"), m("キェ", Some("ky"), "e"), m("キ", Some("k"), "i"),
+    m("ガ", Some("g"), "a"), m("カ", Some("k"), "a"),
+    m("オ", None, "o"), m("エ", None, "e"),
+    m("ウォ", Some("w"), "o"), m("ウェ", Some("w"), "e"), m("ウィ", Some("w"), "i"), m("ウ", None, "u"),
+    m("イェ", Some("y"), "e"), m("イ", None, "i"), m("ア", None, "a"),
+    m("ヴョ", Some("by"), "o"), m("ヴュ", Some("by"), "u"), m("ヴャ", Some("by"), "a"),
+    m("ヲ", None, "o"), m("ヱ", None, "e"), m("ヰ", None, "i"), m("ヮ", Some("w"), "a"),
+    m("ョ", Some("y"), "o"), m("ュ", Some("y"), "u"), m("ャ", Some("y"), "a"),
+    m("ヅ", Some("z"), "u"), m("ヂ", Some("j"), "i"), m("ヶ", Some("k"), "e"),
+    m("ォ", None, "o"), m("ェ", None, "e"), m("ゥ", None, "u"), m("ィ", None, "i"), m("ァ", None, "a"),
+];
+
+/// カタカナからモーラを探す。
+pub fn find_by_text(text: &str) -> Option<&'static Mora> {
+    MORA_LIST.iter().find(|mora| mora.text == text)
+}
+
+/// 音素からモーラを探す。
+pub fn find_by_phonemes(consonant: Option<&str>, vowel: &str) -> Option<&'static Mora> {
+    MORA_LIST
+        .iter()
+        .find(|mora| mora.consonant == consonant && mora.vowel == vowel)
+}
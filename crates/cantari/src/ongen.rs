@@ -7,13 +7,21 @@ use once_cell::sync::OnceCell;
 use regex_macro::regex;
 use serde::Serialize;
 use std::io::Cursor;
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use tokio::sync::RwLock;
 use tracing::{info, info_span, instrument, warn};
 use uuid::Uuid;
 
-use crate::oto::Oto;
+use crate::alias_script::AliasScript;
+use crate::character_yaml::CharacterYaml;
+use crate::oto::{set_sample_cache_limit, Oto};
 use crate::settings::load_settings;
+use crate::text_encoding::decode_text;
+use crate::voicebank_type::VoicebankType;
 
 pub static ONGEN: OnceCell<Arc<RwLock<HashMap<Uuid, Ongen>>>> = OnceCell::new();
 
@@ -27,22 +35,88 @@ pub struct Ongen {
     pub prefix_suffix_map: HashMap<String, (String, String)>,
     #[educe(Debug(ignore))]
     pub oto: Arc<HashMap<String, Oto>>,
+    pub voicebank_type: VoicebankType,
+    pub alias_script: AliasScript,
+    #[educe(Debug(ignore))]
+    pub oto_folders: Vec<OtoFolder>,
+}
+
+/// oto.iniのあるフォルダ毎のoto。
+#[derive(Educe, Clone, Serialize)]
+#[educe(Debug)]
+pub struct OtoFolder {
+    /// 音源のルートからの相対パス。ルート直下の場合は空文字列。
+    pub name: String,
+    pub voicebank_type: VoicebankType,
+    pub alias_script: AliasScript,
+    #[educe(Debug(ignore))]
+    pub oto: Arc<HashMap<String, Oto>>,
+}
+
+/// スタイルから使えるoto。全体のotoと同じく、後にあるフォルダのものが優先される。
+pub struct OtoSet<'a> {
+    maps: Vec<&'a HashMap<String, Oto>>,
+    pub voicebank_type: VoicebankType,
+    pub alias_script: AliasScript,
+}
+
+impl<'a> OtoSet<'a> {
+    pub fn get(&self, alias: &str) -> Option<&'a Oto> {
+        self.maps.iter().rev().find_map(|oto| oto.get(alias))
+    }
+
+    /// ひらがなの読みを、エイリアスの表記に合わせる。
+    pub fn normalize(&self, kana: &str) -> String {
+        self.alias_script.convert(kana)
+    }
 }
 
 impl Ongen {
     #[instrument(skip(existing_uuids))]
     pub async fn new(root: PathBuf, existing_uuids: &[&Uuid]) -> Result<Self> {
-        let character_txt = root.join("character.txt");
-        let character = tokio::fs::read(character_txt).await.unwrap();
-        let character = encoding_rs::SHIFT_JIS.decode(&character).0;
+        let character_yaml = read_character_yaml(&root).await;
+        let encoding_hint = character_yaml
+            .as_ref()
+            .and_then(|x| x.text_file_encoding.clone());
 
         let mut info = HashMap::new();
-        let character_pattern = regex!(r"(?P<key>[^:]+)[=：](?P<value>.+)");
-        for line in character.lines() {
-            if let Some(captures) = character_pattern.captures(line) {
-                let key = captures.name("key").unwrap().as_str().to_string();
-                let value = captures.name("value").unwrap().as_str().to_string();
-                info.insert(key, value);
+        match tokio::fs::read(root.join("character.txt")).await {
+            Ok(character) => {
+                let character = decode_text(&character, encoding_hint.as_deref());
+                let character_pattern = regex!(r"(?P<key>[^:]+)[=：](?P<value>.+)");
+                for line in character.lines() {
+                    if let Some(captures) = character_pattern.captures(line) {
+                        let key = captures.name("key").unwrap().as_str().to_string();
+                        let value = captures.name("value").unwrap().as_str().to_string();
+                        info.insert(key, value);
+                    }
+                }
+            }
+            Err(e) if character_yaml.is_none() => bail!("Failed to read character.txt: {}", e),
+            Err(_) => info!("No character.txt found, using character.yaml"),
+        }
+        // character.yamlの方を優先する
+        if let Some(character_yaml) = &character_yaml {
+            for (key, value) in [
+                ("name", character_yaml.name.clone()),
+                ("image", character_yaml.image.clone()),
+                ("portrait", character_yaml.portrait.clone()),
+                (
+                    "portrait_opacity",
+                    character_yaml.portrait_opacity.map(|x| x.to_string()),
+                ),
+                ("author", character_yaml.author.clone()),
+                ("voice", character_yaml.voice.clone()),
+                ("web", character_yaml.web.clone()),
+                ("version", character_yaml.version.clone()),
+                (
+                    "default_phonemizer",
+                    character_yaml.default_phonemizer.clone(),
+                ),
+            ] {
+                if let Some(value) = value.filter(|x| !x.is_empty()) {
+                    info.insert(key.to_string(), value);
+                }
             }
         }
 
@@ -53,10 +127,12 @@ impl Ongen {
         }
 
         let mut all_oto = HashMap::new();
+        let mut oto_folders = vec![];
 
         for entry in walkdir::WalkDir::new(&root)
             .min_depth(1)
             .max_depth(3)
+            .sort_by_file_name()
             .into_iter()
             .flatten()
         {
@@ -68,7 +144,7 @@ impl Ongen {
 
             info!("Found oto.ini");
             let oto_ini_file = tokio::fs::read(entry.path()).await?;
-            let oto_ini = encoding_rs::SHIFT_JIS.decode(&oto_ini_file).0;
+            let oto_ini = decode_text(&oto_ini_file, encoding_hint.as_deref());
             let oto =
                 Oto::from_oto_ini(&oto_ini, entry.path().parent().unwrap().to_path_buf()).await;
             if oto.is_empty() {
@@ -76,27 +152,75 @@ impl Ongen {
                 continue;
             }
             info!("Loaded {} oto entries", oto.len());
-            all_oto.extend(oto);
+            let voicebank_type = VoicebankType::detect(oto.keys().map(|x| x.as_str()));
+            info!("Detected voicebank type: {}", voicebank_type);
+            let alias_script = AliasScript::detect(oto.keys().map(|x| x.as_str()));
+            info!("Detected alias script: {:?}", alias_script);
+
+            // 同じエイリアスが複数のフォルダにある場合は、後に見つかった方を使う
+            let overridden = oto
+                .keys()
+                .filter(|alias| all_oto.contains_key(*alias))
+                .count();
+            if overridden > 0 {
+                info!("{} aliases override those in earlier folders", overridden);
+            }
+            all_oto.extend(oto.iter().map(|(alias, oto)| (alias.clone(), oto.clone())));
+
+            let folder_name = entry
+                .path()
+                .parent()
+                .unwrap()
+                .strip_prefix(&root)?
+                .components()
+                .map(|x| x.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            oto_folders.push(OtoFolder {
+                name: folder_name,
+                voicebank_type,
+                alias_script,
+                oto: Arc::new(oto),
+            });
         }
 
         if all_oto.is_empty() {
             bail!("No oto.ini found for {}", name);
         }
         info!("Loaded {} oto entries", all_oto.len());
+        // OpenUtauの既定のPhonemizerが分かる場合は、それに合わせる
+        let voicebank_type = character_yaml
+            .as_ref()
+            .and_then(|x| x.default_phonemizer.as_deref())
+            .and_then(VoicebankType::from_phonemizer)
+            .unwrap_or_else(|| VoicebankType::merge(oto_folders.iter().map(|x| x.voicebank_type)));
+        info!("Voicebank type of {}: {}", name, voicebank_type);
+        let alias_script = AliasScript::detect(all_oto.keys().map(|x| x.as_str()));
+        info!("Alias script of {}: {:?}", name, alias_script);
 
         let prefix_suffix_map = if tokio::fs::metadata(root.join("prefix.map")).await.is_ok() {
             info!("Found prefix.map for {}", name);
-            let prefix_map = tokio::fs::read_to_string(root.join("prefix.map")).await?;
+            let prefix_map = tokio::fs::read(root.join("prefix.map")).await?;
+            let prefix_map = decode_text(&prefix_map, encoding_hint.as_deref());
             let mut map = HashMap::new();
             for line in prefix_map.lines() {
                 let mut split = line.split('\t');
-                let key = split.next().unwrap().to_string();
-                let prefix = split.next().unwrap().to_string();
-                let postfix = split.next().unwrap().to_string();
+                let (Some(key), Some(prefix), Some(postfix)) =
+                    (split.next(), split.next(), split.next())
+                else {
+                    continue;
+                };
 
-                map.insert(key, (prefix, postfix));
+                map.insert(key.to_string(), (prefix.to_string(), postfix.to_string()));
             }
             map
+        } else if let Some(map) = character_yaml
+            .as_ref()
+            .map(|x| x.prefix_suffix_map())
+            .filter(|x| !x.is_empty())
+        {
+            info!("Using subbanks in character.yaml for {}", name);
+            map
         } else {
             info!("No prefix.map found for {}", name);
             HashMap::new()
@@ -108,6 +232,9 @@ impl Ongen {
             info,
             prefix_suffix_map,
             oto: Arc::new(all_oto),
+            voicebank_type,
+            alias_script,
+            oto_folders,
         })
     }
 
@@ -115,46 +242,154 @@ impl Ongen {
         self.info.get("name").unwrap().clone()
     }
 
+    /// スタイルで使うotoを返す。
+    pub fn oto_set(&self, style_settings: &StyleSettings) -> OtoSet<'_> {
+        if style_settings.oto_folders.is_empty() {
+            return OtoSet {
+                maps: vec![&self.oto],
+                voicebank_type: self.voicebank_type,
+                alias_script: self.alias_script,
+            };
+        }
+
+        let mut folders = vec![];
+        for folder_name in &style_settings.oto_folders {
+            match self.oto_folders.iter().find(|x| &x.name == folder_name) {
+                Some(folder) => folders.push(folder),
+                None => warn!("Oto folder not found: {:?}", folder_name),
+            }
+        }
+        if folders.is_empty() {
+            warn!("No oto folder matched, using all folders");
+            return OtoSet {
+                maps: vec![&self.oto],
+                voicebank_type: self.voicebank_type,
+                alias_script: self.alias_script,
+            };
+        }
+
+        OtoSet {
+            maps: folders.iter().map(|x| x.oto.as_ref()).collect(),
+            voicebank_type: VoicebankType::merge(folders.iter().map(|x| x.voicebank_type)),
+            alias_script: AliasScript::merge(folders.iter().map(|x| x.alias_script)),
+        }
+    }
+
     pub fn id(&self) -> u32 {
         let uuid_string = self.uuid.to_string();
         let uuid_first_section = uuid_string.split('-').next().unwrap();
         (u32::from_str_radix(uuid_first_section, 16).unwrap() >> 1) & !(0xffu32)
     }
 
-    pub async fn read_image(&self) -> Option<Vec<u8>> {
-        let image_path = self.info.get("image")?.replace('\\', "/");
+    fn read_info_image(&self, key: &str) -> Option<image::DynamicImage> {
+        let image_path = self.info.get(key)?.replace('\\', "/");
         let path = self.root.join(image_path.trim_start_matches('/'));
-        let image = ImageReader::open(path).unwrap().decode().unwrap();
+        match ImageReader::open(&path).map(|x| x.decode()) {
+            Ok(Ok(image)) => Some(image),
+            Ok(Err(e)) => {
+                warn!("Failed to decode {}: {}", path.display(), e);
+                None
+            }
+            Err(e) => {
+                warn!("Failed to open {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    pub async fn read_image(&self) -> Option<Vec<u8>> {
+        let image = self.read_info_image("image")?;
         // 256x256にリサイズされてるので合わせる
         // https://github.com/VOICEVOX/voicevox_resource/blob/main/scripts/resize.sh
         let image = image.resize(256, 256, image::imageops::FilterType::Lanczos3);
         let mut image_buffer = Vec::new();
         image
             .write_to(&mut Cursor::new(&mut image_buffer), image::ImageFormat::Png)
-            .unwrap();
+            .ok()?;
 
         Some(image_buffer)
     }
+
+    /// character.yamlの立ち絵を読む。無い場合はアイコンの画像を使う。
+    pub async fn read_portrait(&self) -> Option<Vec<u8>> {
+        let Some(image) = self.read_info_image("portrait") else {
+            return self.read_image().await;
+        };
+        let mut image = image.to_rgba8();
+        if let Some(opacity) = self
+            .info
+            .get("portrait_opacity")
+            .and_then(|x| x.parse::<f32>().ok())
+        {
+            for pixel in image.pixels_mut() {
+                pixel[3] = (pixel[3] as f32 * opacity.clamp(0.0, 1.0)).round() as u8;
+            }
+        }
+        let mut image_buffer = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut image_buffer), image::ImageFormat::Png)
+            .ok()?;
+
+        Some(image_buffer)
+    }
+
+    /// 利用規約の案内。作者などが分かる場合は添える。
+    pub fn policy(&self) -> String {
+        let mut policy = "元の音源のライセンスに従ってください。".to_string();
+        for (key, label) in [("author", "作者"), ("voice", "声"), ("web", "Web")] {
+            if let Some(value) = self.info.get(key) {
+                policy.push_str(&format!("\n{}：{}", label, value));
+            }
+        }
+        policy
+    }
 }
 
-#[instrument]
-pub async fn setup_ongen() {
-    info!("Setting up ongens...");
-    let mut settings = load_settings().await;
+/// character.yamlを読む。無い場合や読めない場合はNoneを返す。
+async fn read_character_yaml(root: &Path) -> Option<CharacterYaml> {
+    let bytes = tokio::fs::read(root.join("character.yaml")).await.ok()?;
+    match serde_yaml::from_str(&decode_text(&bytes, None)) {
+        Ok(character_yaml) => {
+            info!("Found character.yaml");
+            Some(character_yaml)
+        }
+        Err(e) => {
+            warn!("Failed to parse character.yaml: {}", e);
+            None
+        }
+    }
+}
 
+/// 音源のパスから、character.txtかcharacter.yamlのあるフォルダを探す。
+pub fn find_roots(paths: &[String]) -> Vec<PathBuf> {
     let mut roots = vec![];
-    for path in &settings.paths {
+    for path in paths {
         for file in walkdir::WalkDir::new(path)
             .min_depth(1)
             .max_depth(3)
             .into_iter()
             .flatten()
         {
-            if file.file_type().is_file() && file.file_name() == "character.txt" {
-                roots.push(file.path().parent().unwrap().to_path_buf());
+            if file.file_type().is_file()
+                && (file.file_name() == "character.txt" || file.file_name() == "character.yaml")
+            {
+                let root = file.path().parent().unwrap().to_path_buf();
+                if !roots.contains(&root) {
+                    roots.push(root);
+                }
             }
         }
     }
+    roots
+}
+
+#[instrument]
+pub async fn setup_ongen() {
+    info!("Setting up ongens...");
+    let mut settings = load_settings().await;
+    set_sample_cache_limit(settings.sample_cache_limit);
+
+    let roots = find_roots(&settings.paths);
 
     let mut ongens = HashMap::new();
     for path in roots {
@@ -202,11 +437,15 @@ pub async fn setup_ongen() {
     }
 }
 
+/// 歌唱用のスタイルIDに立てるビット。`Ongen::id`は最上位ビットを使わない。
+pub static SING_STYLE_ID_FLAG: u32 = 1 << 31;
+
 pub async fn get_ongen_style_from_id<'a, 'b>(
     ongens: &'a HashMap<Uuid, Ongen>,
     settings: &'b crate::settings::Settings,
     ongen_id: u32,
 ) -> Option<(&'a Ongen, &'b StyleSettings)> {
+    let ongen_id = ongen_id & !SING_STYLE_ID_FLAG;
     let ongen = ongens
         .values()
         .find(|ongen| ongen.id() == ongen_id & !(0xff))?;
@@ -1,3 +1,4 @@
+use crate::{alias_fallback::AliasFallback, curves::CurveRule, voicebank_type::VoicebankType};
 use serde::{Deserialize, Serialize};
 use serde_with::{base64::Base64, serde_as};
 
@@ -5,6 +6,12 @@ use serde_with::{base64::Base64, serde_as};
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct OngenSettings {
     pub name: Option<String>,
+    /// 音源の種類。未設定の場合は自動で判定したものを使う。
+    #[serde(default)]
+    pub voicebank_type: Option<VoicebankType>,
+    /// エイリアスが見つからない時に代わりに使う読み。組み込みのものより優先される。
+    #[serde(default)]
+    pub alias_fallbacks: Vec<AliasFallback>,
 
     pub style_settings: Vec<StyleSettings>,
 }
@@ -13,6 +20,8 @@ impl Default for OngenSettings {
     fn default() -> Self {
         Self {
             name: None,
+            voicebank_type: None,
+            alias_fallbacks: vec![],
             style_settings: vec![StyleSettings::default()],
         }
     }
@@ -34,6 +43,22 @@ pub struct StyleSettings {
     pub tension: i8,
     pub peak_compression: u8,
     pub voicing: u8,
+    /// 文頭と無音の後に、息のエイリアスを入れるかどうか。
+    #[serde(default)]
+    pub breath: bool,
+
+    /// 使うoto.iniのフォルダ（音源のルートからの相対パス）。空の場合は全てのフォルダを使う。
+    #[serde(default)]
+    pub oto_folders: Vec<String>,
+    /// エイリアスの前に付ける文字列。prefix.mapのプレフィックスの後ろに付く。
+    #[serde(default)]
+    pub alias_prefix: String,
+    /// エイリアスの後ろに付ける文字列。prefix.mapのサフィックスの前に付く。
+    #[serde(default)]
+    pub alias_suffix: String,
+    /// フレーズの終わりやアクセント核などで、声質を変化させるルール。
+    #[serde(default)]
+    pub curve_rules: Vec<CurveRule>,
 }
 
 impl Default for StyleSettings {
@@ -49,6 +74,11 @@ impl Default for StyleSettings {
             tension: 0,
             peak_compression: 86,
             voicing: 100,
+            breath: false,
+            oto_folders: vec![],
+            alias_prefix: String::new(),
+            alias_suffix: String::new(),
+            curve_rules: vec![],
         }
     }
 }
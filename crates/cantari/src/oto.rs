@@ -1,27 +1,65 @@
+use crate::disk_cache::{modified, read_cache, write_cache};
+use crate::frq::load_or_build_frq;
+use crate::lru::{LruStats, SizedLru};
+use crate::settings::{load_settings, DEFAULT_SAMPLE_CACHE_LIMIT};
 use anyhow::anyhow;
 use anyhow::Result;
+use once_cell::sync::Lazy;
 use regex_macro::regex;
-use serde::Serialize;
-use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::warn;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+use xxhash_rust::xxh3::xxh3_64;
 
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+static OTO_CACHE_KIND: &str = "oto";
 
 #[derive(Debug, Clone)]
 pub struct OtoData {
-    pub header: wav_io::header::WavHeader,
+    pub sample_rate: u32,
     pub samples: Vec<f64>,
     pub frq: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Clone)]
 enum OtoCache {
-    Oto(OtoData),
+    Oto(Arc<OtoData>),
     Error(String),
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// 読み込んだ時の、wavとfrqの更新日時。
+type SampleModified = (Option<u64>, Option<u64>);
+
+/// 読み込んだ音声のキャッシュ。同じwavを使うエイリアスで共有する。
+/// wavかfrqの更新日時が変わっていたら読み込み直す。
+static SAMPLE_CACHE: Lazy<Mutex<SizedLru<PathBuf, (SampleModified, OtoCache)>>> =
+    Lazy::new(|| Mutex::new(SizedLru::new(DEFAULT_SAMPLE_CACHE_LIMIT * 1024 * 1024)));
+
+/// 音声のキャッシュの上限をMB単位で設定する。
+pub fn set_sample_cache_limit(limit_mb: usize) {
+    SAMPLE_CACHE
+        .lock()
+        .unwrap()
+        .set_limit(limit_mb * 1024 * 1024);
+}
+
+/// `root`以下の音声をキャッシュから消す。
+pub fn invalidate_sample_cache(root: &Path) {
+    SAMPLE_CACHE
+        .lock()
+        .unwrap()
+        .retain(|path| !path.starts_with(root));
+}
+
+pub fn sample_cache_stats() -> LruStats {
+    SAMPLE_CACHE.lock().unwrap().stats()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Oto {
     pub path: PathBuf,
     pub frq: PathBuf,
@@ -31,9 +69,6 @@ pub struct Oto {
     pub cut_off: f64,
     pub preutter: f64,
     pub overlap: f64,
-
-    #[serde(skip)]
-    cache: Arc<RwLock<Option<OtoCache>>>,
 }
 
 impl Oto {
@@ -67,55 +102,95 @@ impl Oto {
             cut_off: captures["cut_off"].parse()?,
             preutter: captures["preutter"].parse()?,
             overlap: captures["overlap"].parse()?,
-
-            cache: Arc::new(RwLock::new(None)),
         })
     }
 
     pub async fn from_oto_ini(oto_ini: &str, root: PathBuf) -> HashMap<String, Self> {
-        let mut otos = HashMap::new();
-        for line in oto_ini.lines() {
-            let oto = Oto::new(line, root.clone()).await;
-            if let Ok(oto) = oto {
-                for name in &oto.names {
-                    otos.insert(name.clone(), oto.clone());
+        // 同じoto.iniでも、音源の場所が変わるとパスが変わるので、両方をキーにする
+        let cache_key = [root.to_string_lossy().as_bytes(), b"\0", oto_ini.as_bytes()].concat();
+        let entries = match read_cache::<Vec<Self>>(OTO_CACHE_KIND, &cache_key).await {
+            Some(entries) => entries,
+            None => {
+                let mut entries = vec![];
+                for line in oto_ini.lines() {
+                    let oto = Oto::new(line, root.clone()).await;
+                    if let Ok(oto) = oto {
+                        entries.push(oto);
+                    } else {
+                        warn!("Failed to parse oto line: {}", line);
+                    }
                 }
-            } else {
-                warn!("Failed to parse oto line: {}", line);
+                write_cache(OTO_CACHE_KIND, &cache_key, &entries).await;
+                entries
+            }
+        };
+
+        let mut otos = HashMap::new();
+        for oto in entries {
+            for name in &oto.names {
+                otos.insert(name.clone(), oto.clone());
             }
         }
 
         otos
     }
 
-    pub async fn read(&self) -> Result<OtoData> {
-        {
-            let cache = self.cache.read().await;
-            if cache.is_some() {
-                let data = cache.as_ref().unwrap();
-                return match data {
-                    OtoCache::Oto(data) => Ok(data.clone()),
+    async fn sample_modified(&self) -> SampleModified {
+        (modified(&self.path).await, modified(&self.frq).await)
+    }
+
+    pub async fn read(&self) -> Result<Arc<OtoData>> {
+        let sample_modified = self.sample_modified().await;
+        let cached = SAMPLE_CACHE.lock().unwrap().get(&self.path);
+        if let Some((cached_modified, cached)) = cached {
+            if cached_modified == sample_modified {
+                return match cached {
+                    OtoCache::Oto(data) => Ok(data),
                     OtoCache::Error(e) => Err(anyhow!("Failed to read oto: {}", e)),
                 };
             }
+            info!("Sample changed, reloading: {}", self.path.display());
         }
 
-        let data = self.read_inner().await;
-        match &data {
+        let result = self.read_inner().await;
+        // 周波数表を音源のフォルダに書き込んだ場合は、更新日時が変わっている
+        let sample_modified = self.sample_modified().await;
+        match result {
             Ok(data) => {
-                let mut cache = self.cache.write().await;
-                *cache = Some(OtoCache::Oto(data.clone()));
+                let data = Arc::new(data);
+                let size = data.samples.len() * std::mem::size_of::<f64>()
+                    + data.frq.as_ref().map_or(0, |frq| frq.len());
+                SAMPLE_CACHE.lock().unwrap().insert(
+                    self.path.clone(),
+                    (sample_modified, OtoCache::Oto(data.clone())),
+                    size,
+                );
+                Ok(data)
             }
             Err(err) => {
-                let mut cache = self.cache.write().await;
-                *cache = Some(OtoCache::Error(err.to_string()));
+                let message = err.to_string();
+                SAMPLE_CACHE.lock().unwrap().insert(
+                    self.path.clone(),
+                    (sample_modified, OtoCache::Error(message.clone())),
+                    message.len(),
+                );
+                Err(err)
             }
         }
-        data
     }
 
     async fn read_inner(&self) -> Result<OtoData> {
         let file = fs_err::tokio::read(&self.path).await?;
+        let frq = match fs_err::tokio::read(&self.frq).await {
+            Ok(frq) => Some(frq),
+            Err(e) => {
+                warn!("Failed to read frq file: {}", e);
+
+                None
+            }
+        };
+        let hash = frq.is_none().then(|| xxh3_64(&file));
+
         let mut reader = wav_io::reader::Reader::from_vec(file)
             .map_err(|e| anyhow!("Failed to read wav file: {}", e))?;
         let header = reader
@@ -128,21 +203,26 @@ impl Oto {
             samples = wav_io::utils::stereo_to_mono(samples);
         }
 
-        let frq = match fs_err::tokio::read(&self.frq).await {
-            Ok(frq) => Some(frq),
-            Err(e) => {
-                warn!("Failed to read frq file: {}", e);
-
-                None
+        let frq = match (frq, hash) {
+            (Some(frq), _) => Some(frq),
+            (None, Some(hash)) => {
+                let write_to_voicebank = load_settings().await.write_frq;
+                load_or_build_frq(
+                    &self.frq,
+                    hash,
+                    &samples,
+                    header.sample_rate,
+                    write_to_voicebank,
+                )
+                .await
             }
+            (None, None) => None,
         };
 
-        let data = OtoData {
-            header,
-            samples: samples.into_iter().map(|x| x as f64).collect(),
+        Ok(OtoData {
+            sample_rate: header.sample_rate,
+            samples: samples.iter().map(|&x| x as f64).collect(),
             frq,
-        };
-
-        Ok(data)
+        })
     }
 }
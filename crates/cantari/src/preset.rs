@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+/// VOICEVOXのプリセット。
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Preset {
+    pub id: i64,
+    pub name: String,
+    #[serde(rename = "speaker_uuid")]
+    pub speaker_uuid: String,
+    #[serde(rename = "style_id")]
+    pub style_id: u32,
+    pub speed_scale: f32,
+    pub pitch_scale: f32,
+    pub intonation_scale: f32,
+    pub volume_scale: f32,
+    pub pre_phoneme_length: f32,
+    pub post_phoneme_length: f32,
+    // Cantariでは使わないが、エディタに返すために保存しておく
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pause_length: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pause_length_scale: Option<f32>,
+}
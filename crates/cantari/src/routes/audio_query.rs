@@ -1,5 +1,6 @@
 use crate::{
     error::{Error, Result},
+    kana_parser::validate_kana,
     ongen::{get_ongen_style_from_id, ONGEN},
     settings::load_settings,
 };
@@ -26,6 +27,27 @@ pub static SYNTHESIZER: OnceCell<Arc<voicevox_core::tokio::Synthesizer<OpenJtalk
 pub struct AudioQueryParams {
     text: String,
     speaker: u32,
+    #[serde(default)]
+    is_kana: bool,
+    #[serde(default = "default_enable_interrogative_upspeak")]
+    enable_interrogative_upspeak: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ValidateKanaParams {
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AudioQueryFromPresetParams {
+    text: String,
+    preset_id: i64,
+    #[serde(default = "default_enable_interrogative_upspeak")]
+    enable_interrogative_upspeak: bool,
+}
+
+pub fn default_enable_interrogative_upspeak() -> bool {
+    true
 }
 
 #[derive(Debug, Deserialize)]
@@ -147,41 +169,113 @@ async fn modify_pitch(
 pub async fn post_audio_query(
     Query(query): Query<AudioQueryParams>,
 ) -> Result<Json<HttpAudioQuery>> {
+    let audio_query = create_audio_query(
+        &query.text,
+        query.speaker,
+        query.is_kana,
+        query.enable_interrogative_upspeak,
+    )
+    .await?;
+
+    Ok(Json(HttpAudioQuery::from(&audio_query)))
+}
+
+pub async fn post_audio_query_from_preset(
+    Query(query): Query<AudioQueryFromPresetParams>,
+) -> Result<Json<HttpAudioQuery>> {
+    let settings = load_settings().await;
+    let preset = settings
+        .presets
+        .iter()
+        .find(|preset| preset.id == query.preset_id)
+        .ok_or_else(|| Error::PresetNotFound)?;
+
+    let mut audio_query = create_audio_query(
+        &query.text,
+        preset.style_id,
+        false,
+        query.enable_interrogative_upspeak,
+    )
+    .await?;
+    audio_query.speed_scale = preset.speed_scale;
+    audio_query.pitch_scale = preset.pitch_scale;
+    audio_query.intonation_scale = preset.intonation_scale;
+    audio_query.volume_scale = preset.volume_scale;
+    audio_query.pre_phoneme_length = preset.pre_phoneme_length;
+    audio_query.post_phoneme_length = preset.post_phoneme_length;
+
+    Ok(Json(HttpAudioQuery::from(&audio_query)))
+}
+
+async fn create_audio_query(
+    text: &str,
+    speaker: u32,
+    is_kana: bool,
+    enable_interrogative_upspeak: bool,
+) -> Result<crate::model::AudioQueryModel> {
     let synthesizer = get_or_initialize_synthesizer().await;
-    let audio_query = synthesizer
-        .audio_query(&query.text, voicevox_core::StyleId::new(0))
-        .await
-        .map_err(|e| Error::InferenceFailed(anyhow!("Failed to create audio query: {}", e)))?;
+    let audio_query = if is_kana {
+        validate_kana(text).map_err(Error::ParseKanaFailed)?;
+        synthesizer
+            .audio_query_from_kana(text, voicevox_core::StyleId::new(0))
+            .await
+    } else {
+        synthesizer
+            .audio_query(text, voicevox_core::StyleId::new(0))
+            .await
+    }
+    .map_err(|e| Error::InferenceFailed(anyhow!("Failed to create audio query: {}", e)))?;
 
     let mut audio_query = crate::model::AudioQueryModel::from(&audio_query);
     audio_query.accent_phrases = modify_speed(&audio_query.accent_phrases);
-    audio_query.accent_phrases = modify_pitch(&audio_query.accent_phrases, query.speaker).await?;
+    audio_query.accent_phrases = modify_pitch(&audio_query.accent_phrases, speaker).await?;
+    if enable_interrogative_upspeak {
+        audio_query = audio_query.apply_interrogative_upspeak();
+    }
 
     audio_query.pre_phoneme_length = 0.1;
     audio_query.post_phoneme_length = 0.1;
 
-    Ok(Json(HttpAudioQuery::from(&audio_query)))
+    Ok(audio_query)
 }
 
 pub async fn post_accent_phrases(
     Query(query): Query<AudioQueryParams>,
 ) -> Result<Json<Vec<crate::model::AccentPhraseModel>>> {
     let synthesizer = get_or_initialize_synthesizer().await;
-    let accent_phrases = synthesizer
-        .create_accent_phrases(&query.text, voicevox_core::StyleId::new(0))
-        .await
-        .map_err(|e| Error::InferenceFailed(anyhow!("Failed to create accent phrases: {}", e)))?;
+    let accent_phrases = if query.is_kana {
+        validate_kana(&query.text).map_err(Error::ParseKanaFailed)?;
+        synthesizer
+            .create_accent_phrases_from_kana(&query.text, voicevox_core::StyleId::new(0))
+            .await
+    } else {
+        synthesizer
+            .create_accent_phrases(&query.text, voicevox_core::StyleId::new(0))
+            .await
+    }
+    .map_err(|e| Error::InferenceFailed(anyhow!("Failed to create accent phrases: {}", e)))?;
 
     let accent_phrases = accent_phrases
         .iter()
         .map(crate::model::AccentPhraseModel::from)
         .collect::<Vec<_>>();
     let accent_phrases = modify_speed(&accent_phrases);
-    let accent_phrases = modify_pitch(&accent_phrases, query.speaker).await?;
+    let mut accent_phrases = modify_pitch(&accent_phrases, query.speaker).await?;
+    if query.enable_interrogative_upspeak {
+        if let Some(last_accent_phrase) = accent_phrases.last_mut() {
+            *last_accent_phrase = last_accent_phrase.apply_interrogative_upspeak();
+        }
+    }
 
     Ok(Json(accent_phrases))
 }
 
+pub async fn post_validate_kana(Query(query): Query<ValidateKanaParams>) -> Result<Json<bool>> {
+    validate_kana(&query.text).map_err(Error::ParseKanaFailed)?;
+
+    Ok(Json(true))
+}
+
 #[duplicate_item(
     name               synthesizer_method       modifies_speed modifies_pitch;
     [post_mora_data ]  [replace_mora_data]      [true]         [true];
@@ -0,0 +1,62 @@
+use super::synthesis::write_float_wav;
+use crate::error::{Error, Result};
+use anyhow::anyhow;
+use axum::Json;
+use base64::Engine as _;
+
+struct DecodedWave {
+    sample_rate: u32,
+    channels: u16,
+    samples: Vec<f32>,
+}
+
+fn decode_wave(wave: &str) -> anyhow::Result<DecodedWave> {
+    let bytes = base64::engine::general_purpose::STANDARD.decode(wave)?;
+    let mut reader = wav_io::reader::Reader::from_vec(bytes)
+        .map_err(|e| anyhow!("Failed to read wav: {}", e))?;
+    let header = reader
+        .read_header()
+        .map_err(|e| anyhow!("Failed to read wav header: {}", e))?;
+    if !(1..=2).contains(&header.channels) {
+        return Err(anyhow!("Unsupported channel count: {}", header.channels));
+    }
+    let samples = reader
+        .get_samples_f32()
+        .map_err(|e| anyhow!("Failed to read wav samples: {}", e))?;
+
+    Ok(DecodedWave {
+        sample_rate: header.sample_rate,
+        channels: header.channels,
+        samples,
+    })
+}
+
+pub async fn post_connect_waves(Json(waves): Json<Vec<String>>) -> Result<Vec<u8>> {
+    let waves = waves
+        .iter()
+        .map(|wave| decode_wave(wave))
+        .collect::<anyhow::Result<Vec<_>>>()
+        .map_err(Error::ReadWaveFailed)?;
+
+    // 一番高いサンプリングレートに合わせ、ステレオが混ざっていればステレオにする
+    let sample_rate = waves
+        .iter()
+        .map(|wave| wave.sample_rate)
+        .max()
+        .ok_or_else(|| Error::ReadWaveFailed(anyhow!("No waves")))?;
+    let stereo = waves.iter().any(|wave| wave.channels == 2);
+
+    let mut connected = vec![];
+    for wave in waves {
+        let samples =
+            wav_io::resample::linear(wave.samples, wave.channels, wave.sample_rate, sample_rate);
+        let samples = if stereo && wave.channels == 1 {
+            wav_io::utils::mono_to_stereo(samples)
+        } else {
+            samples
+        };
+        connected.extend(samples);
+    }
+
+    Ok(write_float_wav(&connected, sample_rate, stereo))
+}
@@ -56,6 +56,7 @@ pub struct SupportedFeatures {
     pub synthesis_morphing: bool,
     pub manage_library: bool,
     pub return_resource_url: bool,
+    pub sing: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -164,10 +165,11 @@ pub async fn get_engine_manifest() -> Json<EngineManifest> {
             adjust_pitch_scale: true,
             adjust_intonation_scale: true,
             adjust_volume_scale: true,
-            interrogative_upspeak: false,
-            synthesis_morphing: false,
+            interrogative_upspeak: true,
+            synthesis_morphing: true,
             manage_library: false,
             return_resource_url: true,
+            sing: true,
         },
     })
 }
@@ -1,6 +1,11 @@
 pub mod audio_query;
+pub mod connect_waves;
 pub mod info;
+pub mod morphing;
+pub mod presets;
 pub mod settings;
+pub mod sing;
+pub mod song;
 pub mod speakers;
 pub mod synthesis;
 pub mod user_dict;
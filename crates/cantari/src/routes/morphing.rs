@@ -0,0 +1,86 @@
+use super::{
+    audio_query::{default_enable_interrogative_upspeak, HttpAudioQuery},
+    synthesis::{encode_wav, prepare_audio_query, synthesize},
+};
+use crate::{
+    error::{Error, Result},
+    math::mix,
+    ongen::{get_ongen_style_from_id, ONGEN},
+    settings::load_settings,
+};
+use axum::{extract::Query, Json};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::info;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MorphableTargetInfo {
+    pub is_morphable: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SynthesisMorphingQuery {
+    pub base_speaker: u32,
+    pub target_speaker: u32,
+    pub morph_rate: f32,
+    #[serde(default = "default_enable_interrogative_upspeak")]
+    pub enable_interrogative_upspeak: bool,
+}
+
+pub async fn post_morphable_targets(
+    Json(base_speakers): Json<Vec<u32>>,
+) -> Result<Json<Vec<HashMap<String, MorphableTargetInfo>>>> {
+    let ongens = ONGEN.get().unwrap().read().await;
+    let settings = load_settings().await;
+
+    let speaker_ids = ongens
+        .values()
+        .flat_map(|ongen| {
+            let style_count = settings
+                .ongen_settings
+                .get(&ongen.uuid)
+                .map_or(0, |ongen_settings| ongen_settings.style_settings.len());
+            (0..style_count).map(move |i| ongen.id() + i as u32)
+        })
+        .collect::<Vec<u32>>();
+
+    let mut targets = vec![];
+    for base_speaker in base_speakers {
+        get_ongen_style_from_id(&ongens, &settings, base_speaker)
+            .await
+            .ok_or_else(|| Error::SpeakerNotFound)?;
+
+        targets.push(
+            speaker_ids
+                .iter()
+                .map(|id| (id.to_string(), MorphableTargetInfo { is_morphable: true }))
+                .collect(),
+        );
+    }
+
+    Ok(Json(targets))
+}
+
+pub async fn post_synthesis_morphing(
+    Query(query): Query<SynthesisMorphingQuery>,
+    Json(audio_query): Json<HttpAudioQuery>,
+) -> Result<Vec<u8>> {
+    if !(0.0..=1.0).contains(&query.morph_rate) {
+        return Err(Error::InvalidMorphRate);
+    }
+    let audio_query = prepare_audio_query(&audio_query, query.enable_interrogative_upspeak);
+
+    info!(
+        "Morphing {} and {} ({})",
+        query.base_speaker, query.target_speaker, query.morph_rate
+    );
+    let (base_wav, target_wav) = tokio::try_join!(
+        synthesize(&audio_query, query.base_speaker),
+        synthesize(&audio_query, query.target_speaker)
+    )?;
+
+    // 同じAudioQueryから作っているので、発音のタイミングは揃っている
+    let wav = mix(&base_wav, &target_wav, query.morph_rate);
+
+    Ok(encode_wav(&audio_query, wav))
+}
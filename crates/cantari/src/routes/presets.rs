@@ -0,0 +1,68 @@
+use crate::{
+    error::{Error, Result},
+    preset::Preset,
+    settings::{load_settings, write_settings},
+};
+use axum::{extract::Query, Json};
+use serde::Deserialize;
+use tracing::info;
+
+#[derive(Debug, Deserialize)]
+pub struct DeletePresetParams {
+    id: i64,
+}
+
+pub async fn get_presets() -> Json<Vec<Preset>> {
+    let settings = load_settings().await;
+
+    Json(settings.presets)
+}
+
+pub async fn post_add_preset(Json(mut preset): Json<Preset>) -> Result<Json<i64>> {
+    let mut settings = load_settings().await;
+
+    // VOICEVOXと同じく、IDが0以下か既に使われている場合は新しく振り直す
+    if preset.id <= 0 || settings.presets.iter().any(|x| x.id == preset.id) {
+        preset.id = settings.presets.iter().map(|x| x.id).max().unwrap_or(0) + 1;
+    }
+    info!("Adding preset: {} ({})", preset.name, preset.id);
+    let id = preset.id;
+    settings.presets.push(preset);
+
+    write_settings(&settings).await;
+
+    Ok(Json(id))
+}
+
+pub async fn post_update_preset(Json(preset): Json<Preset>) -> Result<Json<i64>> {
+    let mut settings = load_settings().await;
+
+    let current = settings
+        .presets
+        .iter_mut()
+        .find(|x| x.id == preset.id)
+        .ok_or_else(|| Error::PresetNotFound)?;
+    info!("Updating preset: {} ({})", preset.name, preset.id);
+    let id = preset.id;
+    *current = preset;
+
+    write_settings(&settings).await;
+
+    Ok(Json(id))
+}
+
+pub async fn post_delete_preset(Query(params): Query<DeletePresetParams>) -> Result<()> {
+    let mut settings = load_settings().await;
+
+    let index = settings
+        .presets
+        .iter()
+        .position(|x| x.id == params.id)
+        .ok_or_else(|| Error::PresetNotFound)?;
+    info!("Deleting preset: {}", params.id);
+    settings.presets.remove(index);
+
+    write_settings(&settings).await;
+
+    Ok(())
+}
@@ -1,14 +1,25 @@
+use super::synthesis::{alias_affixes, find_alias, PHRASE_START_VOWEL};
 use crate::{
-    error::Result,
+    alias_fallback::FallbackTable,
+    error::{Error, Result},
+    lru::LruStats,
+    math::MidiNote,
+    mora_table::MORA_LIST,
     ongen::{setup_ongen, ONGEN},
     ongen_settings::OngenSettings,
+    oto::sample_cache_stats,
     settings::{load_settings, write_settings},
+    voicebank_type::VoicebankType,
 };
 use anyhow::anyhow;
 use assets::settings_html;
-use axum::{extract::Path, response::Html, Json};
+use axum::{
+    extract::{Path, Query},
+    response::Html,
+    Json,
+};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, io::Cursor};
+use std::{collections::HashMap, io::Cursor, str::FromStr};
 use tracing::{info, info_span};
 use uuid::Uuid;
 
@@ -18,6 +29,8 @@ static DATA_END: &str = r#"</script>"#;
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct FrontendOngen {
     name: String,
+    voicebank_type: VoicebankType,
+    oto_folders: Vec<String>,
 }
 
 fn replace_data(html: &str, id: &str, data: &str) -> String {
@@ -41,7 +54,16 @@ pub async fn get_settings() -> Html<String> {
     let ongens = ONGEN.get().unwrap().read().await;
     let ongens: HashMap<Uuid, FrontendOngen> = ongens
         .iter()
-        .map(|(uuid, ongen)| (*uuid, FrontendOngen { name: ongen.name() }))
+        .map(|(uuid, ongen)| {
+            (
+                *uuid,
+                FrontendOngen {
+                    name: ongen.name(),
+                    voicebank_type: ongen.voicebank_type,
+                    oto_folders: ongen.oto_folders.iter().map(|x| x.name.clone()).collect(),
+                },
+            )
+        })
         .collect();
 
     let html = replace_data(&html, "settings", &settings_json);
@@ -55,6 +77,8 @@ pub struct PutSettingsBody {
     paths: Vec<String>,
     ongen_limit: usize,
     ongen_settings: HashMap<Uuid, OngenSettings>,
+    write_frq: bool,
+    sample_cache_limit: usize,
 }
 
 pub async fn put_settings(body: Json<PutSettingsBody>) -> Result<String> {
@@ -63,6 +87,8 @@ pub async fn put_settings(body: Json<PutSettingsBody>) -> Result<String> {
 
     settings.paths.clone_from(&body.paths);
     settings.ongen_limit = body.ongen_limit;
+    settings.write_frq = body.write_frq;
+    settings.sample_cache_limit = body.sample_cache_limit;
     let mut ongen_settings = body.ongen_settings.clone();
     for (uuid, ongen_setting) in &mut ongen_settings {
         let span = info_span!("ongen", uuid = %uuid);
@@ -139,5 +165,79 @@ pub async fn get_icon(Path(uuid_png): Path<String>) -> Result<Vec<u8>> {
     let ongens = ONGEN.get().unwrap().read().await;
     let ongen = ongens.get(&uuid).ok_or(anyhow!("Ongen not found"))?;
 
-    Ok(ongen.read_image().await.unwrap())
+    Ok(ongen
+        .read_image()
+        .await
+        .unwrap_or_else(|| include_bytes!("../unknown_icon.png").to_vec()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FallbackReportQuery {
+    #[serde(default)]
+    style: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FallbackReportItem {
+    text: String,
+    /// 見つかったエイリアス。見つからなかった場合はNone。
+    alias: Option<String>,
+    /// 代わりに使った読み。
+    fallback: Option<String>,
+}
+
+/// 全てのモーラについて、フレーズ頭で使われるエイリアスと代替の読みを返す。
+pub async fn get_fallback_report(
+    Path(uuid): Path<Uuid>,
+    Query(query): Query<FallbackReportQuery>,
+) -> Result<Json<Vec<FallbackReportItem>>> {
+    let ongens = ONGEN.get().unwrap().read().await;
+    let settings = load_settings().await;
+    let ongen = ongens.get(&uuid).ok_or(Error::CharacterNotFound)?;
+    let ongen_settings = settings
+        .ongen_settings
+        .get(&uuid)
+        .ok_or(Error::CharacterNotFound)?;
+    let style_settings = ongen_settings
+        .style_settings
+        .get(query.style)
+        .ok_or(Error::CharacterNotFound)?;
+
+    let oto_set = ongen.oto_set(style_settings);
+    let voicebank_type = ongen_settings
+        .voicebank_type
+        .unwrap_or(oto_set.voicebank_type);
+    let fallbacks = FallbackTable::new(&ongen_settings.alias_fallbacks);
+    let (prefix, suffix) = alias_affixes(ongen, style_settings, MidiNote::from_str("C4").unwrap());
+
+    // サンプルは読み込まず、エイリアスがあるかだけを見る
+    let report = MORA_LIST
+        .iter()
+        .map(|mora| {
+            let text = kana::kata2hira(mora.text);
+            let found = find_alias(
+                &oto_set,
+                &text,
+                &prefix,
+                &suffix,
+                PHRASE_START_VOWEL,
+                voicebank_type.uses_connected_aliases(),
+                fallbacks,
+            );
+            let (alias, fallback) =
+                found.map_or((None, None), |(alias, fallback)| (Some(alias), fallback));
+            FallbackReportItem {
+                text,
+                alias,
+                fallback,
+            }
+        })
+        .collect();
+
+    Ok(Json(report))
+}
+
+/// 読み込んだ音声のキャッシュの統計を返す。
+pub async fn get_sample_cache_stats() -> Json<LruStats> {
+    Json(sample_cache_stats())
 }
@@ -0,0 +1,293 @@
+use super::synthesis::{encode_wav, synthesize_cancellable, CancelOnDrop};
+use crate::{
+    error::{Error, Result},
+    math::interpolate,
+    model::{
+        AccentPhraseModel, AudioQueryModel, FrameAudioQueryModel, FramePhonemeModel, MoraModel,
+        ScoreModel,
+    },
+    mora_table,
+};
+use axum::{extract::Query, Json};
+use serde::Deserialize;
+use serde_json::Number;
+use tracing::info;
+use worldline::MS_PER_FRAME;
+
+// VOICEVOXの歌唱APIのフレームレート（24000Hz / 256サンプル）
+static FRAME_RATE: f64 = 93.75;
+// 子音の長さ（フレーム）。子音は前の音の終わりに食い込ませる
+static CONSONANT_FRAMES: usize = 8;
+static PAUSE_PHONEME: &str = "pau";
+static VOWEL_PHONEMES: [&str; 12] = ["a", "i", "u", "e", "o", "A", "I", "U", "E", "O", "N", "cl"];
+
+#[derive(Debug, Deserialize)]
+pub struct SingQuery {
+    pub speaker: u32,
+}
+
+struct Phoneme {
+    phoneme: String,
+    frame_length: usize,
+    note_id: Option<String>,
+    key: Option<i32>,
+}
+
+fn key_to_frequency(key: i32) -> f32 {
+    440.0 * 2.0_f32.powf((key as f32 - 69.0) / 12.0)
+}
+
+// 歌唱指導用の話者は使わず、楽譜だけから作るので`speaker`は見ない
+pub async fn post_sing_frame_audio_query(
+    Query(_query): Query<SingQuery>,
+    Json(score): Json<ScoreModel>,
+) -> Result<Json<FrameAudioQueryModel>> {
+    let mut phonemes: Vec<Phoneme> = vec![];
+    for note in &score.notes {
+        let Some(key) = note.key else {
+            phonemes.push(Phoneme {
+                phoneme: PAUSE_PHONEME.to_string(),
+                frame_length: note.frame_length,
+                note_id: note.id.clone(),
+                key: None,
+            });
+            continue;
+        };
+        let mora = mora_table::find_by_text(&kana::hira2kata(&note.lyric))
+            .ok_or_else(|| Error::InvalidLyric(note.lyric.clone()))?;
+
+        let mut vowel_length = note.frame_length;
+        if let Some(consonant) = mora.consonant {
+            // 前の音素が短くて子音を取れない時は、このノートの母音から取る
+            let consonant_length =
+                match phonemes.last_mut().filter(|prev| prev.frame_length / 2 > 0) {
+                    Some(prev) => {
+                        let consonant_length = CONSONANT_FRAMES.min(prev.frame_length / 2);
+                        prev.frame_length -= consonant_length;
+                        consonant_length
+                    }
+                    None => {
+                        let consonant_length = CONSONANT_FRAMES.min(vowel_length / 2);
+                        vowel_length -= consonant_length;
+                        consonant_length
+                    }
+                };
+            if consonant_length > 0 {
+                phonemes.push(Phoneme {
+                    phoneme: consonant.to_string(),
+                    frame_length: consonant_length,
+                    note_id: note.id.clone(),
+                    key: Some(key),
+                });
+            }
+        }
+        phonemes.push(Phoneme {
+            phoneme: mora.vowel.to_string(),
+            frame_length: vowel_length,
+            note_id: note.id.clone(),
+            key: Some(key),
+        });
+    }
+
+    let mut f0 = vec![];
+    let mut volume = vec![];
+    for phoneme in &phonemes {
+        let (phoneme_f0, phoneme_volume) = phoneme
+            .key
+            .map_or((0.0, 0.0), |key| (key_to_frequency(key), 1.0));
+        f0.resize(f0.len() + phoneme.frame_length, phoneme_f0);
+        volume.resize(volume.len() + phoneme.frame_length, phoneme_volume);
+    }
+
+    Ok(Json(FrameAudioQueryModel {
+        f0,
+        volume,
+        phonemes: phonemes
+            .into_iter()
+            .map(|phoneme| FramePhonemeModel {
+                phoneme: phoneme.phoneme,
+                frame_length: phoneme.frame_length,
+                note_id: phoneme.note_id,
+            })
+            .collect(),
+        volume_scale: 1.0,
+        output_sampling_rate: Number::from(24000),
+        output_stereo: false,
+    }))
+}
+
+/// 無声のフレーム（f0が0）を、前後の有声のフレームの値で埋める。
+fn fill_unvoiced(f0: &[f32]) -> Vec<f32> {
+    let Some(first_voiced) = f0.iter().copied().find(|&x| x > 0.0) else {
+        return f0.to_vec();
+    };
+    let mut prev = first_voiced;
+    f0.iter()
+        .map(|&x| {
+            if x > 0.0 {
+                prev = x;
+            }
+            prev
+        })
+        .collect()
+}
+
+/// フレーム単位の音素を、トークと同じAudioQueryに直す。最初の音の開始フレームも返す。
+fn frame_query_to_audio_query(query: &FrameAudioQueryModel) -> Result<(AudioQueryModel, usize)> {
+    let mut accent_phrases: Vec<AccentPhraseModel> = vec![];
+    let mut moras: Vec<MoraModel> = vec![];
+    let mut start_frame = None;
+    let mut frame = 0;
+    let mut phonemes = query.phonemes.iter().peekable();
+    while let Some(phoneme) = phonemes.next() {
+        if phoneme.phoneme == PAUSE_PHONEME {
+            let pause_length = (phoneme.frame_length as f64 / FRAME_RATE) as f32;
+            frame += phoneme.frame_length;
+            if start_frame.is_none() {
+                continue;
+            }
+            if moras.is_empty() {
+                if let Some(pause_mora) = accent_phrases
+                    .last_mut()
+                    .and_then(|accent_phrase| accent_phrase.pause_mora.as_mut())
+                {
+                    pause_mora.vowel_length += pause_length;
+                }
+                continue;
+            }
+            accent_phrases.push(AccentPhraseModel {
+                moras: std::mem::take(&mut moras),
+                accent: 1,
+                pause_mora: Some(MoraModel {
+                    text: "、".to_string(),
+                    consonant: None,
+                    consonant_length: None,
+                    vowel: PAUSE_PHONEME.to_string(),
+                    vowel_length: pause_length,
+                    pitch: 0.0,
+                }),
+                is_interrogative: false,
+                curve_rules: vec![],
+            });
+            continue;
+        }
+        start_frame.get_or_insert(frame);
+
+        let (consonant, vowel) = if VOWEL_PHONEMES.contains(&phoneme.phoneme.as_str()) {
+            (None, phoneme)
+        } else {
+            let vowel = phonemes
+                .next_if(|x| VOWEL_PHONEMES.contains(&x.phoneme.as_str()))
+                .ok_or_else(|| Error::InvalidPhoneme(phoneme.phoneme.clone()))?;
+            (Some(phoneme), vowel)
+        };
+        let mora = mora_table::find_by_phonemes(
+            consonant.map(|x| x.phoneme.as_str()),
+            &vowel.phoneme.to_lowercase(),
+        )
+        .or_else(|| {
+            mora_table::find_by_phonemes(consonant.map(|x| x.phoneme.as_str()), &vowel.phoneme)
+        })
+        .ok_or_else(|| Error::InvalidPhoneme(vowel.phoneme.clone()))?;
+
+        let consonant_frames = consonant.map_or(0, |x| x.frame_length);
+        let vowel_start = frame + consonant_frames;
+        let vowel_f0 = query
+            .f0
+            .iter()
+            .skip(vowel_start)
+            .take(vowel.frame_length)
+            .copied()
+            .filter(|&x| x > 0.0)
+            .collect::<Vec<_>>();
+        let pitch = if vowel_f0.is_empty() {
+            0.0
+        } else {
+            (vowel_f0.iter().sum::<f32>() / vowel_f0.len() as f32).ln()
+        };
+
+        moras.push(MoraModel {
+            text: mora.text.to_string(),
+            consonant: consonant.map(|x| x.phoneme.clone()),
+            consonant_length: consonant.map(|x| (x.frame_length as f64 / FRAME_RATE) as f32),
+            vowel: vowel.phoneme.clone(),
+            vowel_length: (vowel.frame_length as f64 / FRAME_RATE) as f32,
+            pitch,
+        });
+        frame = vowel_start + vowel.frame_length;
+    }
+    if !moras.is_empty() {
+        accent_phrases.push(AccentPhraseModel {
+            moras,
+            accent: 1,
+            pause_mora: None,
+            is_interrogative: false,
+            curve_rules: vec![],
+        });
+    }
+
+    let start_frame = start_frame.unwrap_or(frame);
+    Ok((
+        AudioQueryModel {
+            accent_phrases,
+            speed_scale: 1.0,
+            pitch_scale: 0.0,
+            intonation_scale: 1.0,
+            volume_scale: query.volume_scale,
+            pre_phoneme_length: (start_frame as f64 / FRAME_RATE) as f32,
+            post_phoneme_length: 0.0,
+            output_sampling_rate: query.output_sampling_rate.clone(),
+            output_stereo: query.output_stereo,
+            kana: None,
+        },
+        start_frame,
+    ))
+}
+
+pub async fn post_frame_synthesis(
+    Query(query): Query<SingQuery>,
+    Json(frame_audio_query): Json<FrameAudioQueryModel>,
+) -> Result<Vec<u8>> {
+    let cancel_on_drop = CancelOnDrop::default();
+    let (audio_query, start_frame) = frame_query_to_audio_query(&frame_audio_query)?;
+
+    // f0はMS_PER_FRAME毎、最初の音の開始からにする
+    let base_f0 = fill_unvoiced(&frame_audio_query.f0);
+    let total_frames = frame_audio_query.f0.len();
+    let f0 = (0..)
+        .map(|i| start_frame as f64 + i as f64 * MS_PER_FRAME / 1000.0 * FRAME_RATE)
+        .take_while(|&frame| frame < total_frames as f64)
+        .map(|frame| interpolate(&base_f0, frame))
+        .collect::<Vec<_>>();
+
+    info!(
+        "Singing {} phonemes ({} frames)",
+        frame_audio_query.phonemes.len(),
+        total_frames
+    );
+    let wav = synthesize_cancellable(
+        &audio_query,
+        query.speaker,
+        Some(&f0),
+        cancel_on_drop.flag(),
+    )
+    .await?;
+
+    // 最初の音までを`pre_phoneme_length`にしているので、合成結果は楽譜の頭から始まる
+    let sample_rate = worldline::SAMPLE_RATE as f64;
+    let length = (total_frames as f64 / FRAME_RATE * sample_rate) as usize;
+    let wav = (0..length)
+        .map(|i| {
+            let Some(sample) = wav.get(i) else {
+                return 0.0;
+            };
+            let volume = interpolate(
+                &frame_audio_query.volume,
+                i as f64 / sample_rate * FRAME_RATE,
+            );
+            sample * volume
+        })
+        .collect();
+
+    Ok(encode_wav(&audio_query, wav))
+}
@@ -0,0 +1,301 @@
+use super::synthesis::{
+    alias_affixes, con_vel_to_factor, fit_fades, get_oto, note_pitch_bend, send_segment,
+    spawn_synth_thread, vowel_to_oto, write_float_wav, AdjustedParam, CancelOnDrop, FoundOto,
+    Segment, SegmentRequest, SynthThreadMessage, PHRASE_PADDING, PHRASE_START_VOWEL,
+    PITCH_BEND_TEMPO,
+};
+use crate::{
+    alias_fallback::FallbackTable,
+    curves::{CurveSpan, RenderCurves},
+    error::{Error, Result},
+    math::{MidiNote, Pitch},
+    mora_table,
+    ongen::{get_ongen_style_from_id, ONGEN},
+    settings::load_settings,
+    ust::{read_project, Song, SongNote},
+};
+use axum::{body::Bytes, extract::Query};
+use serde::Deserialize;
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tracing::{debug, info, warn};
+use worldline::{SynthRequest, MS_PER_FRAME};
+
+// 前のノートの終わりとこれ以上離れていなければ、つながっているものとして扱う（ms）
+static CONNECT_THRESHOLD: f64 = 1.0;
+
+#[derive(Debug, Deserialize)]
+pub struct SongSynthesisQuery {
+    pub speaker: u32,
+}
+
+pub async fn post_song_synthesis(
+    Query(query): Query<SongSynthesisQuery>,
+    body: Bytes,
+) -> Result<Vec<u8>> {
+    let cancel_on_drop = CancelOnDrop::default();
+    let song = read_project(&body).map_err(Error::ReadProjectFailed)?;
+    let wav = render_song(&song, query.speaker, cancel_on_drop.flag()).await?;
+
+    Ok(write_float_wav(&wav, worldline::SAMPLE_RATE, false))
+}
+
+/// 歌詞の最後のモーラの母音を返す。`a か`のようなエイリアスでも良い。
+fn lyric_vowel(lyric: &str) -> Option<&'static str> {
+    let kana = kana::hira2kata(lyric.rsplit(' ').next().unwrap_or(lyric));
+    let chars = kana.chars().collect::<Vec<_>>();
+    (1..=chars.len().min(2)).rev().find_map(|n| {
+        mora_table::find_by_text(&chars[chars.len() - n..].iter().collect::<String>())
+            .map(|mora| mora.vowel)
+    })
+}
+
+/// 伸ばす音（`+`、`ー`など）は、前の音の母音にする。
+fn lyric_to_oto(lyric: &str, prev_vowel: Option<&str>) -> String {
+    let is_extender = lyric.starts_with('+') || lyric == "ー" || lyric == "-";
+    match prev_vowel {
+        Some(prev_vowel) if is_extender => mora_table::find_by_phonemes(None, prev_vowel)
+            .map_or(lyric.to_string(), |mora| kana::kata2hira(mora.text)),
+        _ => lyric.to_string(),
+    }
+}
+
+/// 曲全体のf0を作る。`i`番目の値は、曲の頭の`PHRASE_PADDING`ms前から`i * MS_PER_FRAME`ms後のもの。
+fn song_f0(notes: &[SongNote], length: f64) -> Vec<f32> {
+    let Some(first) = notes.first() else {
+        return vec![];
+    };
+    let frames = (length / MS_PER_FRAME).ceil() as usize;
+    let mut current = 0;
+    (0..frames)
+        .map(|i| {
+            let time = i as f64 * MS_PER_FRAME - PHRASE_PADDING;
+            while notes
+                .get(current + 1)
+                .is_some_and(|next| next.position <= time)
+            {
+                current += 1;
+            }
+            let note = &notes[current];
+            // 次のノートのピッチ曲線は、ノートの前から始まっていることがある
+            let cents = notes
+                .get(current + 1)
+                .and_then(|next| next.pitch_at(time))
+                .or_else(|| note.pitch_at(time))
+                .unwrap_or_else(|| {
+                    let note = if time < first.position { first } else { note };
+                    note.note_num as f64 * 100.0
+                });
+            (440.0 * 2.0_f64.powf((cents / 100.0 - 69.0) / 12.0)) as f32
+        })
+        .collect()
+}
+
+struct SongUnit<'a> {
+    note: &'a SongNote,
+    found: FoundOto<'a>,
+    /// キーシフト後の音高。
+    pitch: Pitch,
+    /// 前のノートとつながっているかどうか。
+    is_connected: bool,
+}
+
+/// 読み込んだ曲を合成し、曲の頭から始まる`worldline::SAMPLE_RATE`の波形を返す。
+pub async fn render_song(
+    song: &Song,
+    speaker: u32,
+    cancelled: Arc<AtomicBool>,
+) -> Result<Vec<f32>> {
+    let ongens = ONGEN.get().unwrap().read().await;
+    let settings = load_settings().await;
+
+    let (ongen, style_settings) = get_ongen_style_from_id(&ongens, &settings, speaker)
+        .await
+        .ok_or_else(|| Error::CharacterNotFound)?;
+    let oto_set = ongen.oto_set(style_settings);
+    let ongen_settings = settings.ongen_settings.get(&ongen.uuid);
+    let voicebank_type = ongen_settings
+        .and_then(|ongen_settings| ongen_settings.voicebank_type)
+        .unwrap_or(oto_set.voicebank_type);
+    let fallbacks = FallbackTable::new(
+        ongen_settings.map_or(&[], |ongen_settings| &ongen_settings.alias_fallbacks),
+    );
+
+    let mut units: Vec<SongUnit> = vec![];
+    let mut prev: Option<(&SongNote, Option<&str>)> = None;
+    for note in &song.notes {
+        let is_connected =
+            prev.is_some_and(|(prev, _)| (note.position - prev.end()).abs() < CONNECT_THRESHOLD);
+        let prev_vowel = prev.filter(|_| is_connected).and_then(|(_, vowel)| vowel);
+        let lyric = lyric_to_oto(&note.lyric, prev_vowel);
+        let pitch = Pitch {
+            note: MidiNote(note.note_num),
+            cents: 0.0,
+        }
+        .shift(
+            style_settings.key_shift as i32,
+            MidiNote::from_str("C1").unwrap(),
+            MidiNote::from_str("B7").unwrap(),
+        );
+        let (prefix, suffix) = alias_affixes(ongen, style_settings, pitch.note);
+        let found = get_oto(
+            &oto_set,
+            &lyric,
+            &prefix,
+            &suffix,
+            &prev_vowel.map_or(PHRASE_START_VOWEL.to_string(), vowel_to_oto),
+            voicebank_type.uses_connected_aliases(),
+            fallbacks,
+        )
+        .await;
+
+        let Some(found) = found else {
+            warn!("No oto found for {:?}", note.lyric);
+            prev = None;
+            continue;
+        };
+        prev = Some((note, lyric_vowel(&lyric)));
+        units.push(SongUnit {
+            note,
+            found,
+            pitch,
+            is_connected,
+        });
+    }
+
+    let adjusted_params = units
+        .iter()
+        .enumerate()
+        .map(|(i, unit)| {
+            let oto = unit.found.oto;
+            AdjustedParam::new(
+                unit.note.preutter.unwrap_or(oto.preutter),
+                unit.note.overlap.unwrap_or(oto.overlap),
+                con_vel_to_factor(unit.note.velocity),
+                unit.is_connected.then(|| units[i - 1].note.length),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let song_end = song.notes.last().map_or(0.0, |note| note.end());
+    let mut curves = RenderCurves::new(
+        song_f0(&song.notes, song_end + PHRASE_PADDING * 2.0),
+        style_settings,
+    );
+    let spans = units
+        .iter()
+        .enumerate()
+        .map(|(i, unit)| CurveSpan {
+            start: unit.note.position + PHRASE_PADDING,
+            end: unit.note.end() + PHRASE_PADDING,
+            is_phrase_start: !unit.is_connected,
+            is_phrase_end: units.get(i + 1).is_none_or(|next| !next.is_connected),
+            is_accent: false,
+            rules: &[],
+        })
+        .collect::<Vec<_>>();
+    curves.apply_rules(&style_settings.curve_rules, &spans);
+
+    info!("Rendering {} notes", units.len());
+    let (message_sender, message_receiver) = std::sync::mpsc::channel::<SynthThreadMessage>();
+    let wav_task = spawn_synth_thread(message_receiver, cancelled.clone());
+
+    let mut segment: Option<Segment> = None;
+    for (i, (unit, adjusted_param)) in units.iter().zip(adjusted_params.iter()).enumerate() {
+        if cancelled.load(Ordering::Relaxed) {
+            break;
+        }
+        // 休符などで途切れたら、そこまでを合成する
+        if !unit.is_connected {
+            if let Some(segment) = segment.take() {
+                if !send_segment(&message_sender, &curves, segment) {
+                    break;
+                }
+            }
+        }
+
+        let oto = unit.found.oto;
+        let oto_data = &unit.found.oto_data;
+        let next_adjusted_param = units
+            .get(i + 1)
+            .filter(|next| next.is_connected)
+            .map(|_| &adjusted_params[i + 1]);
+
+        let (start, skip) = adjusted_param.start_and_skip(unit.note.position);
+        let adjusted_length = adjusted_param.length(unit.note.length, next_adjusted_param);
+        let (fade, next_fade, volume) = fit_fades(
+            adjusted_param.fade(),
+            next_adjusted_param.map_or(0.0, AdjustedParam::fade),
+            adjusted_length,
+        );
+
+        let pitch_bend = note_pitch_bend(
+            style_settings,
+            &curves.f0,
+            start - skip,
+            adjusted_length + skip + 100.0,
+            MidiNote(unit.note.note_num).to_frequency(),
+        );
+
+        let flags = &unit.note.flags;
+        let request = SynthRequest {
+            sample_fs: oto_data.sample_rate as i32,
+            sample: oto_data.samples.clone(),
+            frq: oto_data.frq.clone(),
+            tone: unit.pitch.note.0 as i32,
+            con_vel: unit.note.velocity,
+            offset: oto.offset,
+            required_length: adjusted_length + skip + 100.0,
+            consonant: oto.consonant - skip,
+            cut_off: oto.cut_off - skip * oto.cut_off.signum(),
+            volume: unit.note.intensity * volume,
+            modulation: unit.note.modulation,
+            tempo: PITCH_BEND_TEMPO,
+            pitch_bend,
+            // スタイルの性別・張り・息・声の強さは曲線で渡すので、ノートのフラグだけを使う
+            flag_g: flags.g.unwrap_or(0),
+            flag_o: flags.o.unwrap_or(0),
+            flag_p: flags.p.unwrap_or(style_settings.peak_compression as _),
+            flag_mt: flags.mt.unwrap_or(0),
+            flag_mb: flags.mb.unwrap_or(0),
+            flag_mv: flags.mv.unwrap_or(100),
+        };
+
+        debug!("Adding {:?} at {:?}", unit.found.alias, start);
+        segment
+            .get_or_insert_with(Segment::default)
+            .push(SegmentRequest {
+                alias: unit.found.alias.clone(),
+                request,
+                start,
+                skip,
+                length: adjusted_length,
+                fade,
+                next_fade,
+            });
+    }
+    if let Some(segment) = segment.take() {
+        send_segment(&message_sender, &curves, segment);
+    }
+    let _ = message_sender.send(SynthThreadMessage::Finish);
+
+    let mut wav = wav_task
+        .await
+        .unwrap()
+        .ok_or_else(|| Error::SynthesisCancelled)?;
+
+    // 頭の余白を除き、最後のノートの終わりまでの長さにする
+    let padding = (PHRASE_PADDING / 1000.0 * worldline::SAMPLE_RATE as f64) as usize;
+    wav.drain(..padding.min(wav.len()));
+    let length = (song_end / 1000.0 * worldline::SAMPLE_RATE as f64) as usize;
+    if wav.len() < length {
+        wav.resize(length, 0.0);
+    }
+
+    Ok(wav)
+}
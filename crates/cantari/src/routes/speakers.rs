@@ -1,5 +1,5 @@
 use crate::error::{Error, Result};
-use crate::ongen::ONGEN;
+use crate::ongen::{ONGEN, SING_STYLE_ID_FLAG};
 use crate::settings::load_settings;
 
 use axum::{
@@ -7,6 +7,7 @@ use axum::{
     Json,
 };
 use base64::Engine as _;
+use duplicate::duplicate_item;
 use serde::{Deserialize, Serialize};
 use tracing::warn;
 use uuid::Uuid;
@@ -17,6 +18,7 @@ pub struct VvSpeaker {
     pub speaker_uuid: String,
     pub styles: Vec<VvStyle>,
     pub version: String,
+    pub supported_features: SupportedFeatures,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -34,6 +36,9 @@ pub struct VvStyleInfo {
     pub voice_samples: Vec<String>,
 }
 
+// UTAU音源同士ならどの組み合わせでもモーフィングできる
+pub static PERMITTED_SYNTHESIS_MORPHING: &str = "ALL";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SupportedFeatures {
     pub permitted_synthesis_morphing: String,
@@ -46,7 +51,12 @@ pub struct VvStyle {
     pub r#type: String,
 }
 
-pub async fn get_speakers() -> Result<Json<Vec<VvSpeaker>>> {
+#[duplicate_item(
+    handler_name   style_type       base_id;
+    [get_speakers] ["talk"]         [speaker.id()];
+    [get_singers]  ["frame_decode"] [(speaker.id() | SING_STYLE_ID_FLAG)];
+)]
+pub async fn handler_name() -> Result<Json<Vec<VvSpeaker>>> {
     let ongens = ONGEN.get().unwrap().read().await;
     let settings = load_settings().await;
 
@@ -69,11 +79,14 @@ pub async fn get_speakers() -> Result<Json<Vec<VvSpeaker>>> {
                 .enumerate()
                 .map(|(i, style_settings)| VvStyle {
                     name: style_settings.name.clone(),
-                    id: speaker.id() + i as u32,
-                    r#type: "talk".to_string(),
+                    id: base_id + i as u32,
+                    r#type: style_type.to_string(),
                 })
                 .collect(),
             version: "N/A".to_string(),
+            supported_features: SupportedFeatures {
+                permitted_synthesis_morphing: PERMITTED_SYNTHESIS_MORPHING.to_string(),
+            },
         };
 
         speakers.push(speaker);
@@ -97,7 +110,12 @@ pub struct SpeakerInfoQuery {
     pub resource_format: ResourceFormat,
 }
 
-pub async fn get_speaker_info(
+#[duplicate_item(
+    handler_name       base_id;
+    [get_speaker_info] [speaker.id()];
+    [get_singer_info]  [(speaker.id() | SING_STYLE_ID_FLAG)];
+)]
+pub async fn handler_name(
     Query(query): axum::extract::Query<SpeakerInfoQuery>,
     Host(host): Host,
 ) -> Result<Json<VvSpeakerInfo>> {
@@ -135,7 +153,7 @@ pub async fn get_speaker_info(
             let default_portrait = match ongen_settings.style_settings[0].portrait {
                 Some(ref portrait) => portrait.clone(),
                 None => speaker
-                    .read_image()
+                    .read_portrait()
                     .await
                     .unwrap_or_else(|| include_bytes!("../unknown_portrait.png").to_vec()),
             };
@@ -154,7 +172,7 @@ pub async fn get_speaker_info(
             break;
         }
         let style_info = VvStyleInfo {
-            id: speaker.id() + i as u32,
+            id: base_id + i as u32,
 
             icon: match query.resource_format {
                 ResourceFormat::Base64 => style_settings.icon.as_ref().map_or_else(
@@ -184,7 +202,7 @@ pub async fn get_speaker_info(
     }
 
     let info = VvSpeakerInfo {
-        policy: "元の音源のライセンスに従ってください。".to_string(),
+        policy: speaker.policy(),
         portrait: default_portrait_data,
         style_infos,
     };
@@ -230,7 +248,7 @@ pub async fn get_portrait(Path((uuid, i)): Path<(Uuid, u32)>) -> Result<Vec<u8>>
     let portrait = match style_settings.portrait {
         Some(ref portrait) => portrait.clone(),
         None => speaker
-            .read_image()
+            .read_portrait()
             .await
             .unwrap_or_else(|| include_bytes!("../unknown_portrait.png").to_vec()),
     };
@@ -1,91 +1,270 @@
-use super::audio_query::HttpAudioQuery;
+use super::audio_query::{default_enable_interrogative_upspeak, HttpAudioQuery};
 use crate::{
-    error::Result,
-    math::{smooth, MidiNote},
-    model::{AudioQueryModel, MoraModel},
-    ongen::{get_ongen_style_from_id, ONGEN},
+    alias_fallback::FallbackTable,
+    curves::{CurveRule, CurveSpan, RenderCurves},
+    error::{Error, Result},
+    math::{interpolate, smooth, MidiNote, Pitch},
+    model::{is_long_vowel_mora, AudioQueryModel, MoraModel},
+    ongen::{get_ongen_style_from_id, Ongen, OtoSet, ONGEN},
+    ongen_settings::StyleSettings,
     oto::{Oto, OtoData},
     settings::load_settings,
 };
-use async_recursion::async_recursion;
 use axum::{extract::Query, Json};
+use futures::{StreamExt, TryStreamExt};
 use itertools::izip;
-use once_cell::sync::Lazy;
 use serde::Deserialize;
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    io::Write,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, Sender},
+        Arc,
+    },
+};
 use tracing::{debug, info, warn};
 use wav_io::header::WavHeader;
 use worldline::{SynthRequest, MS_PER_FRAME};
+use zip::write::SimpleFileOptions;
 
-static PHRASE_PADDING: f64 = 500.0;
-
-static OTO_FALLBACKS: Lazy<HashMap<&str, &str>> = Lazy::new(|| {
-    let mut map = HashMap::new();
-    map.insert("お", "を");
-    map.insert("を", "お");
-    map.insert("ず", "づ");
-    map.insert("じ", "ぢ");
-    map.insert("づ", "ず");
-    map.insert("ぢ", "じ");
-    map
-});
+pub static PHRASE_PADDING: f64 = 500.0;
+pub static PHRASE_START_VOWEL: &str = "-";
+// UTAUのピッチベンドは5tick毎なので、このテンポで5ms毎になる
+pub static PITCH_BEND_TEMPO: f64 = 125.0;
+static PITCH_BEND_INTERVAL: f64 = 60.0 / PITCH_BEND_TEMPO / 96.0 * 1000.0;
+// 息のエイリアス。前にあるものが優先される
+static BREATH_ALIASES: [&str; 5] = ["息", "吸", "br", "breath", "R"];
+// 無音のうち息に使う割合と、息の長さの範囲（ms）
+static BREATH_RATE: f64 = 0.6;
+static MIN_BREATH_LENGTH: f64 = 80.0;
+static MAX_BREATH_LENGTH: f64 = 400.0;
+// 語尾のエイリアス（`a R`、`a -`など）。前にあるものが優先される
+static ENDING_ALIASES: [&str; 2] = ["R", "-"];
+// 無音のうち語尾に使う割合と、語尾の長さの範囲（ms）。息と重ならないようにする
+static ENDING_RATE: f64 = 1.0 - BREATH_RATE;
+static MIN_ENDING_LENGTH: f64 = 50.0;
+static MAX_ENDING_LENGTH: f64 = 300.0;
+// 語尾の音のうち、フェードアウトさせる割合
+static ENDING_FADE_RATE: f64 = 0.5;
 
 #[derive(Debug, Deserialize)]
 pub struct AudioQueryQuery {
     pub speaker: u32,
+    #[serde(default = "default_enable_interrogative_upspeak")]
+    pub enable_interrogative_upspeak: bool,
+}
+
+#[derive(Debug)]
+pub struct FoundOto<'a> {
+    pub alias: String,
+    pub oto: &'a Oto,
+    pub oto_data: Arc<OtoData>,
+    /// 前の母音から繋がるエイリアス（`a か`など）かどうか。
+    pub is_connected: bool,
 }
 
-async fn get_oto<'a>(
-    oto: &'a HashMap<String, Oto>,
+/// `kana`のエイリアスを探す。見つからない場合は`fallbacks`の読みを順に試す。
+pub async fn get_oto<'a>(
+    oto: &OtoSet<'a>,
     kana: &str,
     prefix: &str,
     suffix: &str,
     prev_vowel: &str,
-) -> Option<(String, &'a Oto, OtoData)> {
-    get_oto_inner(oto, kana, prefix, suffix, prev_vowel, true).await
+    connect: bool,
+    fallbacks: FallbackTable<'_>,
+) -> Option<FoundOto<'a>> {
+    if let Some(found) = get_oto_by_kana(oto, kana, prefix, suffix, prev_vowel, connect).await {
+        return Some(found);
+    }
+
+    for fallback in fallbacks.candidates(kana) {
+        if let Some(found) =
+            get_oto_by_kana(oto, &fallback, prefix, suffix, prev_vowel, connect).await
+        {
+            info!(
+                "No oto found for {:?} {:?} {:?} {:?}, using fallback {:?} ({:?})",
+                prefix, prev_vowel, kana, suffix, fallback, found.alias
+            );
+            return Some(found);
+        }
+    }
+
+    None
 }
 
-#[async_recursion]
-async fn get_oto_inner<'a>(
-    oto: &'a HashMap<String, Oto>,
+/// `get_oto`と同じ順にエイリアスを探すが、サンプルは読み込まない。
+/// `(エイリアス, 代わりに使った読み)`を返す。
+pub fn find_alias(
+    oto: &OtoSet<'_>,
     kana: &str,
     prefix: &str,
     suffix: &str,
     prev_vowel: &str,
-    find_fallback: bool,
-) -> Option<(String, &'a Oto, OtoData)> {
-    let aliases = [
-        // 連続音（音質が安定しないので無効化）
-        format!("{}{} {}{}", prefix, prev_vowel, kana, suffix),
-        // 単独音2
-        format!("{}{}{}", prefix, kana, suffix),
+    connect: bool,
+    fallbacks: FallbackTable<'_>,
+) -> Option<(String, Option<String>)> {
+    std::iter::once(None)
+        .chain(fallbacks.candidates(kana).into_iter().map(Some))
+        .find_map(|fallback| {
+            let kana = fallback.as_deref().unwrap_or(kana);
+            let (connected, aliases) = kana_aliases(oto, kana, prefix, suffix, prev_vowel, connect);
+            connected
+                .into_iter()
+                .chain(aliases)
+                .find(|alias| oto.get(alias).is_some())
+                .map(|alias| (alias, fallback))
+        })
+}
+
+/// `kana`のエイリアスの候補を、前の母音から繋がるものと、それ以外とに分けて優先順に返す。
+fn kana_aliases(
+    oto: &OtoSet<'_>,
+    kana: &str,
+    prefix: &str,
+    suffix: &str,
+    prev_vowel: &str,
+    connect: bool,
+) -> (Option<String>, Vec<String>) {
+    let kana = oto.normalize(kana);
+    let is_phrase_start = prev_vowel == PHRASE_START_VOWEL;
+    // 連続音
+    let connected = (connect && !is_phrase_start)
+        .then(|| format!("{}{} {}{}", prefix, prev_vowel, kana, suffix));
+    let single_start = format!("{}{} {}{}", prefix, PHRASE_START_VOWEL, kana, suffix);
+    let mut aliases = vec![];
+    if is_phrase_start {
+        // フレーズ頭の連続音
+        aliases.push(single_start.clone());
+    }
+    // 単独音2
+    aliases.push(format!("{}{}{}", prefix, kana, suffix));
+    if !is_phrase_start {
         // 単独音
-        format!("{}- {}{}", prefix, kana, suffix),
-    ];
+        aliases.push(single_start);
+    }
+    (connected, aliases)
+}
+
+async fn get_oto_by_kana<'a>(
+    oto: &OtoSet<'a>,
+    kana: &str,
+    prefix: &str,
+    suffix: &str,
+    prev_vowel: &str,
+    connect: bool,
+) -> Option<FoundOto<'a>> {
+    let (connected, aliases) = kana_aliases(oto, kana, prefix, suffix, prev_vowel, connect);
+    if let Some(found) = find_first_oto(oto, connected, true).await {
+        return Some(found);
+    }
+    find_first_oto(oto, aliases, false).await
+}
 
-    for alias in aliases.into_iter() {
+/// `aliases`のうち、最初に読み込めたものを返す。
+async fn find_first_oto<'a>(
+    oto: &OtoSet<'a>,
+    aliases: impl IntoIterator<Item = String>,
+    is_connected: bool,
+) -> Option<FoundOto<'a>> {
+    for alias in aliases {
         if let Some(oto) = oto.get(&alias) {
             match oto.read().await {
-                Ok(oto_data) => return Some((alias, oto, oto_data.clone())),
+                Ok(oto_data) => {
+                    return Some(FoundOto {
+                        alias,
+                        oto,
+                        oto_data,
+                        is_connected,
+                    })
+                }
                 Err(e) => warn!("Failed to read oto data for {:?}: {:?}", alias, e),
             }
         }
     }
 
-    if find_fallback {
-        if let Some(fallback) = OTO_FALLBACKS.get(kana) {
-            info!(
-                "No oto found for {:?} {:?} {:?} {:?}, trying fallback {:?}",
-                prefix, prev_vowel, kana, suffix, fallback
-            );
-            return get_oto_inner(oto, fallback, prefix, suffix, prev_vowel, false).await;
-        }
+    None
+}
+
+/// CVVCの母音から子音へのつなぎ（`a k`など）を探す。
+async fn get_vc_oto<'a>(
+    oto: &OtoSet<'a>,
+    vowel: &str,
+    consonant: &str,
+    prefix: &str,
+    suffix: &str,
+) -> Option<FoundOto<'a>> {
+    let mut aliases = vec![format!("{}{} {}{}", prefix, vowel, consonant, suffix)];
+    // 拗音（ky、gyなど）はyを省いたものも探す
+    if let Some(base_consonant) = consonant.strip_suffix('y').filter(|x| !x.is_empty()) {
+        aliases.push(format!("{}{} {}{}", prefix, vowel, base_consonant, suffix));
     }
+    find_first_oto(oto, aliases, true).await
+}
 
-    None
+/// 息のエイリアスを探す。
+async fn get_breath_oto<'a>(oto: &OtoSet<'a>, prefix: &str, suffix: &str) -> Option<FoundOto<'a>> {
+    let aliases = BREATH_ALIASES
+        .iter()
+        .flat_map(|breath| {
+            [
+                format!("{}{}{}", prefix, breath, suffix),
+                breath.to_string(),
+                format!("{} {}", PHRASE_START_VOWEL, breath),
+            ]
+        })
+        .collect::<Vec<_>>();
+    find_first_oto(oto, aliases, false).await
 }
 
-fn con_vel_to_factor(con_vel: f64) -> f64 {
+/// 母音の後の語尾のエイリアスを探す。
+async fn get_ending_oto<'a>(
+    oto: &OtoSet<'a>,
+    vowel: &str,
+    prefix: &str,
+    suffix: &str,
+) -> Option<FoundOto<'a>> {
+    let aliases = ENDING_ALIASES
+        .iter()
+        .flat_map(|ending| {
+            [
+                format!("{}{} {}{}", prefix, vowel, ending, suffix),
+                format!("{} {}", vowel, ending),
+            ]
+        })
+        .collect::<Vec<_>>();
+    find_first_oto(oto, aliases, true).await
+}
+
+/// エイリアスの前後に付ける文字列を返す。
+pub fn alias_affixes(
+    ongen: &Ongen,
+    style_settings: &StyleSettings,
+    note: MidiNote,
+) -> (String, String) {
+    let (prefix, suffix) = ongen
+        .prefix_suffix_map
+        .get(note.to_string().as_str())
+        .map_or(("", ""), |x| (&x.0, &x.1));
+    (
+        format!("{}{}", prefix, style_settings.alias_prefix),
+        format!("{}{}", style_settings.alias_suffix, suffix),
+    )
+}
+
+/// f0から、`base_freq`を基準にしたピッチベンド（セント）を作る。
+pub fn get_pitch_bend(f0: &[f32], start: f64, length: f64, base_freq: f32) -> Vec<i32> {
+    let count = (length / PITCH_BEND_INTERVAL).ceil() as usize + 1;
+    (0..count)
+        .map(|i| {
+            let time = start + i as f64 * PITCH_BEND_INTERVAL;
+            let freq = interpolate(f0, time / MS_PER_FRAME);
+            (1200.0 * (freq / base_freq).log2()).round() as i32
+        })
+        .collect()
+}
+
+pub fn con_vel_to_factor(con_vel: f64) -> f64 {
     2.0f64.powf((100.0 - con_vel) / 100.0)
 }
 
@@ -97,18 +276,68 @@ fn text_to_oto(text: &str) -> String {
     kana::kata2hira(text)
 }
 
+/// 長音や語尾上げのモーラは、直前の母音を伸ばした音として扱う。
+fn mora_to_oto(mora: &MoraModel) -> String {
+    if !is_long_vowel_mora(mora) {
+        return text_to_oto(&mora.text);
+    }
+    match mora.vowel.as_str() {
+        "a" | "A" => "あ",
+        "i" | "I" => "い",
+        "u" | "U" => "う",
+        "e" | "E" => "え",
+        "o" | "O" => "お",
+        "N" => "ん",
+        _ => return text_to_oto(&mora.text),
+    }
+    .to_string()
+}
+
+/// 連続音のエイリアスに使う母音を返す。促音や無音の後はフレーズ頭として扱う。
+pub fn vowel_to_oto(vowel: &str) -> String {
+    match vowel {
+        "cl" | "pau" => PHRASE_START_VOWEL.to_string(),
+        "N" => "n".to_string(),
+        _ => vowel.to_lowercase(),
+    }
+}
+
 #[derive(Debug)]
 struct Prerender<'a> {
     alias: String,
     freq: f32,
     oto: Option<&'a Oto>,
-    oto_data: Option<OtoData>,
+    oto_data: Option<Arc<OtoData>>,
     mora: &'a MoraModel,
-    note: MidiNote,
+    /// キーシフト後の音高。
+    pitch: Pitch,
+    is_connected: bool,
+    /// 先行発声を合わせる位置（ms）。
+    position: f64,
+    /// 次の音の先行発声までの長さ（ms）。
+    length: f64,
+    /// 子音の長さ（ms）。
+    consonant_length: Option<f64>,
+    /// アクセント核のモーラかどうか。
+    is_accent: bool,
+    /// アクセント句に指定された曲線の変化。
+    curve_rules: &'a [CurveRule],
+    kind: UnitKind,
 }
 
-#[derive(Debug)]
-struct AdjustedParam {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnitKind {
+    /// モーラの音（VCを含む）。
+    Mora,
+    /// 無音の後に入れた息。
+    Breath,
+    /// 無音の前に入れた語尾。
+    Ending,
+}
+
+/// 前の音に収まるように調整した先行発声とオーバーラップ。
+#[derive(Debug, Default)]
+pub struct AdjustedParam {
     preutter: f64,
     overlap: f64,
     skip: f64,
@@ -116,42 +345,468 @@ struct AdjustedParam {
 
 #[derive(Debug)]
 #[allow(clippy::large_enum_variant)]
-enum SynthThreadMessage {
-    Request(String, SynthRequest, f64, f64, f64, f64, f64),
-    F0(Vec<f32>),
-    Do,
+pub enum SynthThreadMessage {
+    Request(SegmentRequest),
+    Curves(RenderCurves),
+    /// ここまでの区間を合成し、指定した位置（ms）に置く。
+    Do(f64),
+    Finish,
+}
+
+/// 区間に入れる音。
+#[derive(Debug)]
+pub struct SegmentRequest {
+    pub alias: String,
+    pub request: SynthRequest,
+    /// 開始位置（ms）。`Segment`の中では波形の頭から、送る時は区間の開始位置から。
+    pub start: f64,
+    pub skip: f64,
+    pub length: f64,
+    pub fade: f64,
+    pub next_fade: f64,
+}
+
+/// 無音で区切った、まとめて合成する区間。
+#[derive(Debug, Default)]
+pub struct Segment {
+    requests: Vec<SegmentRequest>,
+    /// 区間の最後の音の終わり（ms）。
+    end: f64,
+}
+
+impl Segment {
+    pub fn push(&mut self, request: SegmentRequest) {
+        self.end = self.end.max(request.start + request.length);
+        self.requests.push(request);
+    }
+}
+
+/// ドロップされた時にキャンセルされたことにするフラグ。
+#[derive(Debug, Default)]
+pub struct CancelOnDrop(Arc<AtomicBool>);
+
+impl CancelOnDrop {
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        self.0.clone()
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// 区間の曲線を送って合成させる。合成スレッドが終了していたらfalseを返す。
+pub fn send_segment(
+    sender: &Sender<SynthThreadMessage>,
+    curves: &RenderCurves,
+    segment: Segment,
+) -> bool {
+    // 後の音の方が、オーバーラップが長くて前から始まることもある
+    let Some(offset) = segment
+        .requests
+        .iter()
+        .map(|request| request.start)
+        .reduce(f64::min)
+    else {
+        return true;
+    };
+    let offset = (offset / MS_PER_FRAME).floor() * MS_PER_FRAME;
+    for request in segment.requests {
+        let request = SegmentRequest {
+            start: request.start - offset,
+            ..request
+        };
+        if sender.send(SynthThreadMessage::Request(request)).is_err() {
+            return false;
+        }
+    }
+
+    let end_frame =
+        (((segment.end + PHRASE_PADDING) / MS_PER_FRAME).ceil() as usize).min(curves.f0.len());
+    let start_frame = ((offset / MS_PER_FRAME) as usize).min(end_frame);
+    sender
+        .send(SynthThreadMessage::Curves(
+            curves.slice(start_frame..end_frame),
+        ))
+        .is_ok()
+        && sender.send(SynthThreadMessage::Do(offset)).is_ok()
+}
+
+/// 合成スレッドを起動する。`cancelled`がtrueになるか、`Finish`の前に送信側がドロップされたらNoneを返す。
+pub fn spawn_synth_thread(
+    message_receiver: Receiver<SynthThreadMessage>,
+    cancelled: Arc<AtomicBool>,
+) -> tokio::task::JoinHandle<Option<Vec<f32>>> {
+    tokio::task::spawn_blocking(move || {
+        let mut synthesizer = worldline::PhraseSynth::new();
+        let mut wav: Vec<f32> = vec![];
+        for message in message_receiver.iter() {
+            if cancelled.load(Ordering::Relaxed) {
+                info!("Synthesis cancelled");
+                return None;
+            }
+            match message {
+                SynthThreadMessage::Request(request) => {
+                    debug!(
+                        "Adding request: {:?} {:?} {:?} {:?} {:?} {:?}",
+                        request.alias,
+                        request.start,
+                        request.skip,
+                        request.length,
+                        request.fade,
+                        request.next_fade
+                    );
+                    synthesizer.add_request(
+                        &request.request,
+                        request.start,
+                        request.skip,
+                        request.length,
+                        request.fade,
+                        request.next_fade,
+                    );
+                }
+                SynthThreadMessage::Curves(curves) => {
+                    debug!("Setting curves");
+                    let to_f64 =
+                        |curve: &[f32]| curve.iter().map(|x| *x as f64).collect::<Vec<f64>>();
+                    synthesizer.set_curves(
+                        &to_f64(&curves.f0),
+                        &to_f64(&curves.gender),
+                        &to_f64(&curves.tension),
+                        &to_f64(&curves.breathiness),
+                        &to_f64(&curves.voicing),
+                    );
+                }
+                SynthThreadMessage::Do(offset) => {
+                    info!("Synthesizing...");
+                    let segment = synthesizer.synth();
+                    synthesizer = worldline::PhraseSynth::new();
+
+                    let offset = (offset / 1000.0 * worldline::SAMPLE_RATE as f64) as usize;
+                    if wav.len() < offset + segment.len() {
+                        wav.resize(offset + segment.len(), 0.0);
+                    }
+                    for (i, sample) in segment.iter().enumerate() {
+                        wav[offset + i] += sample;
+                    }
+                }
+                SynthThreadMessage::Finish => return Some(wav),
+            }
+        }
+
+        // Finishが来る前に送信側がドロップされた
+        None
+    })
+}
+
+/// 曲線を変化させる区間を作る。音の無いところ（息を含む）をフレーズの区切りとする。
+fn curve_spans<'a>(otos: &[Prerender<'a>]) -> Vec<CurveSpan<'a>> {
+    let is_voiced =
+        |prerender: &Prerender| prerender.oto.is_some() && prerender.kind == UnitKind::Mora;
+    otos.iter()
+        .enumerate()
+        .filter(|(_, current)| is_voiced(current))
+        .map(|(i, current)| CurveSpan {
+            start: current.position + PHRASE_PADDING,
+            end: current.position + current.length + PHRASE_PADDING,
+            is_phrase_start: i == 0 || !is_voiced(&otos[i - 1]),
+            is_phrase_end: otos.get(i + 1).is_none_or(|next| !is_voiced(next)),
+            is_accent: current.is_accent,
+            rules: current.curve_rules,
+        })
+        .collect()
+}
+
+/// 文末と無音（`pau`）の前に、前の音の母音からつながる語尾を入れる。
+/// 文末では`post_phoneme_length`（ms）の中に収める。
+async fn insert_endings<'a>(
+    otos: Vec<Prerender<'a>>,
+    oto_set: &OtoSet<'a>,
+    ongen: &Ongen,
+    style_settings: &StyleSettings,
+    post_phoneme_length: f64,
+) -> Vec<Prerender<'a>> {
+    let mut inserted = Vec::with_capacity(otos.len());
+    let mut otos = otos.into_iter().peekable();
+    while let Some(current) = otos.next() {
+        let silence_length = match otos.peek() {
+            _ if current.oto.is_none() => None,
+            None => Some(post_phoneme_length),
+            Some(next) if next.oto.is_none() && next.mora.vowel == "pau" => {
+                Some(next.mora.vowel_length as f64 * 1000.0)
+            }
+            Some(_) => None,
+        };
+        let ending_length =
+            silence_length.map_or(0.0, |length| (length * ENDING_RATE).min(MAX_ENDING_LENGTH));
+        let vowel = vowel_to_oto(&current.mora.vowel);
+
+        let ending = if ending_length >= MIN_ENDING_LENGTH && vowel != PHRASE_START_VOWEL {
+            let (prefix, suffix) = alias_affixes(ongen, style_settings, current.pitch.note);
+            get_ending_oto(oto_set, &vowel, &prefix, &suffix)
+                .await
+                .map(|found| {
+                    debug!("Appending ending {:?} ({}ms)", found.alias, ending_length);
+                    Prerender {
+                        freq: current.freq,
+                        alias: found.alias,
+                        oto: Some(found.oto),
+                        oto_data: Some(found.oto_data),
+                        mora: current.mora,
+                        pitch: current.pitch,
+                        is_connected: found.is_connected,
+                        position: current.position + current.length,
+                        length: ending_length,
+                        consonant_length: None,
+                        is_accent: false,
+                        curve_rules: current.curve_rules,
+                        kind: UnitKind::Ending,
+                    }
+                })
+        } else {
+            None
+        };
+        inserted.push(current);
+        inserted.extend(ending);
+    }
+
+    inserted
+}
+
+/// 文頭と無音（`pau`）の後に、次の音の子音に向かって息を入れる。
+/// 文頭では`pre_phoneme_length`（ms）の中に収める。
+async fn insert_breaths<'a>(
+    otos: Vec<Prerender<'a>>,
+    oto_set: &OtoSet<'a>,
+    ongen: &Ongen,
+    style_settings: &StyleSettings,
+    pre_phoneme_length: f64,
+) -> Vec<Prerender<'a>> {
+    let mut inserted = Vec::with_capacity(otos.len());
+    let mut silence_length = Some(pre_phoneme_length);
+    for current in otos {
+        if current.oto.is_none() {
+            silence_length =
+                (current.mora.vowel == "pau").then_some(current.mora.vowel_length as f64 * 1000.0);
+            inserted.push(current);
+            continue;
+        }
+
+        let breath_length = silence_length
+            .take()
+            .map_or(0.0, |length| (length * BREATH_RATE).min(MAX_BREATH_LENGTH));
+        if breath_length >= MIN_BREATH_LENGTH {
+            let (prefix, suffix) = alias_affixes(ongen, style_settings, current.pitch.note);
+            if let Some(found) = get_breath_oto(oto_set, &prefix, &suffix).await {
+                let consonant_length = current.consonant_length.unwrap_or(0.0);
+                debug!("Inserting breath {:?} ({}ms)", found.alias, breath_length);
+                inserted.push(Prerender {
+                    freq: current.freq,
+                    alias: found.alias,
+                    oto: Some(found.oto),
+                    oto_data: Some(found.oto_data),
+                    mora: current.mora,
+                    pitch: current.pitch,
+                    is_connected: false,
+                    position: current.position - consonant_length - breath_length,
+                    length: breath_length + consonant_length,
+                    consonant_length: None,
+                    is_accent: false,
+                    curve_rules: current.curve_rules,
+                    kind: UnitKind::Breath,
+                });
+            }
+        }
+        inserted.push(current);
+    }
+
+    inserted
 }
 
 impl AdjustedParam {
-    fn fade(&self) -> f64 {
+    /// `prev_length`は前の音の長さで、フレーズ頭では`None`。
+    pub fn new(preutter: f64, overlap: f64, factor: f64, prev_length: Option<f64>) -> Self {
+        // フレーズ頭ではクロスフェードする相手がいないので、先行発声だけを使う
+        let Some(prev_length) = prev_length else {
+            return Self {
+                preutter,
+                overlap: 0.0,
+                skip: 0.0,
+            };
+        };
+        let real_preutter = preutter * factor;
+        let real_overlap = overlap * factor;
+
+        if prev_length / 2.0 < real_preutter - real_overlap {
+            let at_preutter = real_preutter / (real_preutter - real_overlap) * prev_length;
+            let at_overlap = real_overlap / (real_preutter - real_overlap) * prev_length;
+            let at_skip = real_preutter - at_preutter;
+            Self {
+                preutter: at_preutter,
+                overlap: at_overlap,
+                skip: at_skip,
+            }
+        } else {
+            Self {
+                preutter: real_preutter,
+                overlap: real_overlap,
+                skip: 0.0,
+            }
+        }
+    }
+
+    pub fn fade(&self) -> f64 {
         self.overlap.max(0.0)
     }
 
     fn shift(&self) -> f64 {
         self.overlap.min(0.0)
     }
+
+    /// `position`の音を置く位置と、サンプルの頭を飛ばす長さを返す。
+    pub fn start_and_skip(&self, position: f64) -> (f64, f64) {
+        let start = position + PHRASE_PADDING + self.shift() - self.preutter;
+        let skip = self.skip.max(0.0) - start.min(0.0);
+        (start.max(0.0), skip)
+    }
+
+    /// 次の音とのオーバーラップを含めた、合成する長さを返す。
+    pub fn length(&self, length: f64, next: Option<&Self>) -> f64 {
+        length + self.preutter + next.map_or(0.0, |next| next.overlap - next.preutter)
+    }
+}
+
+/// 前後のクロスフェードが音の長さを超える場合は縮め、代わりに音量を下げる。
+/// `(fade, next_fade, volume)`を返す。
+pub fn fit_fades(fade: f64, next_fade: f64, length: f64) -> (f64, f64, f64) {
+    if fade + next_fade <= length {
+        return (fade, next_fade, 1.0);
+    }
+    warn!(
+        "Fade length exceeds adjusted length: {:?} + {:?} > {:?}",
+        fade, next_fade, length
+    );
+
+    let volume = ((length - next_fade) / fade).clamp(0.0, 1.0);
+    let fade = (length - next_fade).max(0.0);
+    let next_fade = (length - fade).max(0.0);
+    (fade, next_fade, volume)
+}
+
+/// 音のピッチベンドを作る。
+pub fn note_pitch_bend(
+    style_settings: &StyleSettings,
+    f0: &[f32],
+    start: f64,
+    length: f64,
+    base_freq: f32,
+) -> Vec<i32> {
+    // ささやきの時は周波数が意味を持たないので、ピッチベンドを使わない
+    if style_settings.whisper {
+        vec![0]
+    } else {
+        get_pitch_bend(f0, start, length, base_freq)
+    }
 }
 
 pub async fn post_synthesis(
     Query(query): Query<AudioQueryQuery>,
     Json(audio_query): Json<HttpAudioQuery>,
 ) -> Result<Vec<u8>> {
-    let ongens = ONGEN.get().unwrap().read().await;
-    let settings = load_settings().await;
-    let audio_query = AudioQueryModel::from(&audio_query)
+    let audio_query = prepare_audio_query(&audio_query, query.enable_interrogative_upspeak);
+    let wav = synthesize(&audio_query, query.speaker).await?;
+
+    Ok(encode_wav(&audio_query, wav))
+}
+
+pub async fn post_cancellable_synthesis(
+    Query(query): Query<AudioQueryQuery>,
+    Json(audio_query): Json<HttpAudioQuery>,
+) -> Result<Vec<u8>> {
+    // 接続が切れるとこのFutureごとドロップされるので、合成スレッドも止まる
+    let cancel_on_drop = CancelOnDrop::default();
+    let audio_query = prepare_audio_query(&audio_query, query.enable_interrogative_upspeak);
+    let wav =
+        synthesize_cancellable(&audio_query, query.speaker, None, cancel_on_drop.flag()).await?;
+
+    Ok(encode_wav(&audio_query, wav))
+}
+
+pub async fn post_multi_synthesis(
+    Query(query): Query<AudioQueryQuery>,
+    Json(audio_queries): Json<Vec<HttpAudioQuery>>,
+) -> Result<Vec<u8>> {
+    let parallelism = std::thread::available_parallelism().map_or(1, |x| x.get());
+    let wavs: Vec<Vec<u8>> = futures::stream::iter(audio_queries)
+        .map(|audio_query| async move {
+            let audio_query = prepare_audio_query(&audio_query, query.enable_interrogative_upspeak);
+            let wav = synthesize(&audio_query, query.speaker).await?;
+            Ok::<_, Error>(encode_wav(&audio_query, wav))
+        })
+        .buffered(parallelism)
+        .try_collect()
+        .await?;
+
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (i, wav) in wavs.iter().enumerate() {
+        zip.start_file(format!("{:03}.wav", i + 1), options)
+            .and_then(|_| zip.write_all(wav).map_err(Into::into))
+            .map_err(|e| Error::SynthesisFailed(e.into()))?;
+    }
+    let zip = zip.finish().map_err(|e| Error::SynthesisFailed(e.into()))?;
+
+    Ok(zip.into_inner())
+}
+
+/// 速度などの調整を反映した、合成用のAudioQueryを作る。
+pub fn prepare_audio_query(
+    audio_query: &HttpAudioQuery,
+    enable_interrogative_upspeak: bool,
+) -> AudioQueryModel {
+    let mut audio_query = AudioQueryModel::from(audio_query);
+    if enable_interrogative_upspeak {
+        audio_query = audio_query.apply_interrogative_upspeak();
+    }
+    audio_query
         .apply_speed_scale(audio_query.speed_scale)
         .apply_pitch_scale(audio_query.pitch_scale)
-        .apply_intonation_scale(audio_query.intonation_scale);
+        .apply_intonation_scale(audio_query.intonation_scale)
+}
 
-    let (ongen, style_settings) = get_ongen_style_from_id(&ongens, &settings, query.speaker)
-        .await
-        .ok_or_else(|| crate::error::Error::CharacterNotFound)?;
+/// `prepare_audio_query`で作ったAudioQueryを合成し、`worldline::SAMPLE_RATE`の波形を返す。
+/// 波形は、`pre_phoneme_length`の無音から始まり、`post_phoneme_length`の無音で終わる。
+pub async fn synthesize(audio_query: &AudioQueryModel, speaker: u32) -> Result<Vec<f32>> {
+    synthesize_cancellable(audio_query, speaker, None, Arc::new(AtomicBool::new(false))).await
+}
 
-    let mut synthesizer = worldline::PhraseSynth::new();
+/// `f0`を指定すると、モーラの音高の代わりに使う（`MS_PER_FRAME`毎、最初のモーラの子音から）。
+/// `cancelled`がtrueになったら、合成を途中で止めて`Error::SynthesisCancelled`を返す。
+pub async fn synthesize_cancellable(
+    audio_query: &AudioQueryModel,
+    speaker: u32,
+    f0: Option<&[f32]>,
+    cancelled: Arc<AtomicBool>,
+) -> Result<Vec<f32>> {
+    let ongens = ONGEN.get().unwrap().read().await;
+    let settings = load_settings().await;
 
-    let mut prev_vowel = "-".to_string();
+    let (ongen, style_settings) = get_ongen_style_from_id(&ongens, &settings, speaker)
+        .await
+        .ok_or_else(|| crate::error::Error::CharacterNotFound)?;
+    let oto_set = ongen.oto_set(style_settings);
+    let ongen_settings = settings.ongen_settings.get(&ongen.uuid);
+    let voicebank_type = ongen_settings
+        .and_then(|ongen_settings| ongen_settings.voicebank_type)
+        .unwrap_or(oto_set.voicebank_type);
+    debug!("Voicebank type: {}", voicebank_type);
+    let fallbacks = FallbackTable::new(
+        ongen_settings.map_or(&[], |ongen_settings| &ongen_settings.alias_fallbacks),
+    );
 
-    let mut f0 = Vec::new();
+    let mut prev_vowel = PHRASE_START_VOWEL.to_string();
 
     let moras = audio_query
         .accent_phrases
@@ -164,9 +819,33 @@ pub async fn post_synthesis(
             moras
         })
         .collect::<Vec<&MoraModel>>();
+    let accent_flags = audio_query
+        .accent_phrases
+        .iter()
+        .flat_map(|x| {
+            let mut flags = (0..x.moras.len())
+                .map(|i| i + 1 == x.accent)
+                .collect::<Vec<bool>>();
+            if x.pause_mora.is_some() {
+                flags.push(false);
+            }
+            flags
+        })
+        .collect::<Vec<bool>>();
+    let phrase_curve_rules = audio_query
+        .accent_phrases
+        .iter()
+        .flat_map(|x| {
+            let count = x.moras.len() + x.pause_mora.iter().len();
+            std::iter::repeat_n(x.curve_rules.as_slice(), count)
+        })
+        .collect::<Vec<&[CurveRule]>>();
 
     let mut otos: Vec<Prerender> = vec![];
-    for mora in moras.iter() {
+    let mut position = moras.first().map_or(0.0, |mora| {
+        (mora.consonant_length.unwrap_or(0.0) * 1000.0) as f64
+    });
+    for (i, mora) in moras.iter().enumerate() {
         let pitch = if mora.pitch == 0.0 {
             5.5f32
         } else {
@@ -177,28 +856,50 @@ pub async fn post_synthesis(
         } else {
             pitch.exp()
         };
-        let kana = text_to_oto(&mora.text);
-        let freq_midi = MidiNote::from_frequency(freq);
-        let freq_midi_number = freq_midi.0 as i32;
-        let freq_midi_number = (freq_midi_number + style_settings.key_shift as i32).clamp(
-            MidiNote::from_str("C1").unwrap().0 as i32,
-            MidiNote::from_str("B7").unwrap().0 as i32,
-        ) as u8;
-        let freq_midi = MidiNote(freq_midi_number);
-        let (prefix, suffix) = ongen
-            .prefix_suffix_map
-            .get(freq_midi.to_string().as_str())
-            .map_or(("", ""), |x| (&x.0, &x.1));
-        match get_oto(&ongen.oto, &kana, prefix, suffix, &prev_vowel).await {
-            Some((alias, oto, oto_data)) => {
-                prev_vowel = mora.vowel.to_lowercase();
+        let kana = mora_to_oto(mora);
+        let note_pitch = Pitch::from_frequency(freq).shift(
+            style_settings.key_shift as i32,
+            MidiNote::from_str("C1").unwrap(),
+            MidiNote::from_str("B7").unwrap(),
+        );
+        let (prefix, suffix) = alias_affixes(ongen, style_settings, note_pitch.note);
+        let length = ((mora.vowel_length
+            + moras
+                .get(i + 1)
+                .map_or(0.0, |next| next.consonant_length.unwrap_or(0.0)))
+            * 1000.0) as f64;
+        let consonant_length = mora.consonant_length.map(|x| (x * 1000.0) as f64);
+        let found = get_oto(
+            &oto_set,
+            &kana,
+            &prefix,
+            &suffix,
+            &prev_vowel,
+            voicebank_type.uses_connected_aliases(),
+            fallbacks,
+        )
+        .await;
+        prev_vowel = if found.is_some() {
+            vowel_to_oto(&mora.vowel)
+        } else {
+            PHRASE_START_VOWEL.to_string()
+        };
+        match found {
+            Some(found) => {
                 otos.push(Prerender {
                     freq,
-                    alias,
-                    oto: Some(oto),
-                    oto_data: Some(oto_data),
+                    alias: found.alias,
+                    oto: Some(found.oto),
+                    oto_data: Some(found.oto_data),
                     mora,
-                    note: freq_midi,
+                    pitch: note_pitch,
+                    is_connected: found.is_connected,
+                    position,
+                    length,
+                    consonant_length,
+                    is_accent: accent_flags[i],
+                    curve_rules: phrase_curve_rules[i],
+                    kind: UnitKind::Mora,
                 });
             }
             None => {
@@ -211,12 +912,96 @@ pub async fn post_synthesis(
                     oto: None,
                     oto_data: None,
                     mora,
-                    note: freq_midi,
+                    pitch: note_pitch,
+                    is_connected: false,
+                    position,
+                    length,
+                    consonant_length,
+                    is_accent: accent_flags[i],
+                    curve_rules: phrase_curve_rules[i],
+                    kind: UnitKind::Mora,
                 });
             }
         }
+        position += length;
     }
 
+    // CVVC：次のモーラが単独音で子音から始まる場合、母音の後ろにVCを挟む
+    let mut units: Vec<Prerender> = Vec::with_capacity(otos.len());
+    let mut otos_iter = otos.into_iter().peekable();
+    while let Some(mut current) = otos_iter.next() {
+        let vc = match otos_iter.peek() {
+            Some(next)
+                if voicebank_type.uses_vc_aliases()
+                    && current.oto.is_some()
+                    && next.oto.is_some()
+                    && !next.is_connected =>
+            {
+                match (next.mora.consonant.as_ref(), next.consonant_length) {
+                    (Some(consonant), Some(consonant_length)) if consonant_length > 0.0 => {
+                        let (prefix, suffix) =
+                            alias_affixes(ongen, style_settings, current.pitch.note);
+                        get_vc_oto(
+                            &oto_set,
+                            &vowel_to_oto(&current.mora.vowel),
+                            consonant,
+                            &prefix,
+                            &suffix,
+                        )
+                        .await
+                        .map(|found| (found, next.position - consonant_length, consonant_length))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        match vc {
+            Some((found, vc_position, vc_length)) => {
+                current.length = vc_position - current.position;
+                let vc = Prerender {
+                    freq: current.freq,
+                    alias: found.alias,
+                    oto: Some(found.oto),
+                    oto_data: Some(found.oto_data),
+                    mora: current.mora,
+                    pitch: current.pitch,
+                    is_connected: found.is_connected,
+                    position: vc_position,
+                    length: vc_length,
+                    consonant_length: None,
+                    is_accent: current.is_accent,
+                    curve_rules: current.curve_rules,
+                    kind: UnitKind::Mora,
+                };
+                units.push(current);
+                units.push(vc);
+            }
+            None => units.push(current),
+        }
+    }
+    let otos = insert_endings(
+        units,
+        &oto_set,
+        ongen,
+        style_settings,
+        (audio_query.post_phoneme_length / audio_query.speed_scale * 1000.0) as f64,
+    )
+    .await;
+    let otos = if style_settings.breath {
+        insert_breaths(
+            otos,
+            &oto_set,
+            ongen,
+            style_settings,
+            (audio_query.pre_phoneme_length / audio_query.speed_scale * 1000.0) as f64,
+        )
+        .await
+    } else {
+        otos
+    };
+
     let aliases = otos
         .iter()
         .map(|prerender| prerender.alias.clone())
@@ -234,8 +1019,7 @@ pub async fn post_synthesis(
                 let Some(oto) = &current.oto else {
                     return 100.0;
                 };
-                if let Some(consonant_length) = current.mora.consonant_length {
-                    let consonant_length = (consonant_length * 1000.0) as f64;
+                if let Some(consonant_length) = current.consonant_length {
                     let oto_consonant_length =
                         (oto.preutter - oto.overlap) / 2.0 + (oto.consonant - oto.preutter) / 2.0;
                     let vel = factor_to_con_vel((consonant_length) / oto_consonant_length)
@@ -251,9 +1035,12 @@ pub async fn post_synthesis(
             })
             .collect();
 
-        sum_length = otos.first().map_or(0.0, |x| {
-            ((x.mora.consonant_length.unwrap_or(0.0)) * 1000.0) as f64
-        });
+        // 文末の語尾は`post_phoneme_length`の中に収めるので、長さに含めない
+        sum_length = otos
+            .iter()
+            .rev()
+            .find(|x| x.kind != UnitKind::Ending)
+            .map_or(0.0, |x| x.position + x.length);
 
         let adjusted_params: Vec<AdjustedParam> = otos
             .iter()
@@ -261,91 +1048,56 @@ pub async fn post_synthesis(
             .enumerate()
             .map(|(i, (current, con_vel))| {
                 let Some(oto) = &current.oto else {
-                    return AdjustedParam {
-                        preutter: 0.0,
-                        overlap: 0.0,
-                        skip: 0.0,
-                    };
-                };
-                let prev_mora = if i == 0 {
-                    return AdjustedParam {
-                        preutter: oto.preutter,
-                        overlap: 0.0,
-                        skip: 0.0,
-                    };
-                } else {
-                    &moras[i - 1]
+                    return AdjustedParam::default();
                 };
-                let prev_length = ((prev_mora.vowel_length
-                    + current.mora.consonant_length.unwrap_or(0.0))
-                    * 1000.0) as f64;
-                let real_preutter = oto.preutter * con_vel_to_factor(*con_vel);
-                let real_overlap = oto.overlap * con_vel_to_factor(*con_vel);
-
-                if prev_length / 2.0 < real_preutter - real_overlap {
-                    let at_preutter = real_preutter / (real_preutter - real_overlap) * prev_length;
-                    let at_overlap = real_overlap / (real_preutter - real_overlap) * prev_length;
-                    let at_skip = real_preutter - at_preutter;
-                    AdjustedParam {
-                        preutter: at_preutter,
-                        overlap: at_overlap,
-                        skip: at_skip,
-                    }
-                } else {
-                    AdjustedParam {
-                        preutter: real_preutter,
-                        overlap: real_overlap,
-                        skip: 0.0,
-                    }
-                }
+                let prev_length = (i > 0)
+                    .then(|| &otos[i - 1])
+                    .filter(|prev| prev.oto.is_some())
+                    .map(|prev| prev.length);
+                AdjustedParam::new(
+                    oto.preutter,
+                    oto.overlap,
+                    con_vel_to_factor(*con_vel),
+                    prev_length,
+                )
             })
             .collect();
 
         debug!("Consonant velocities: {:?}", &con_vels);
         debug!("Adjusted params: {:?}", &adjusted_params);
 
-        let (message_sender, message_receiver) = std::sync::mpsc::channel::<SynthThreadMessage>();
-
-        let wav_task = tokio::task::spawn_blocking(move || {
-            for message in message_receiver.iter() {
-                match message {
-                    SynthThreadMessage::Request(
-                        alias,
-                        request,
-                        start,
-                        skip,
-                        length,
-                        fade,
-                        next_fade,
-                    ) => {
-                        debug!(
-                            "Adding request: {:?} {:?} {:?} {:?} {:?} {:?}",
-                            alias, start, skip, length, fade, next_fade
-                        );
-                        synthesizer.add_request(&request, start, skip, length, fade, next_fade);
-                    }
-                    SynthThreadMessage::F0(f0) => {
-                        debug!("Setting f0");
-                        synthesizer.set_curves(
-                            &f0.iter().map(|x| *x as f64).collect::<Vec<f64>>(),
-                            &vec![0.5; f0.len()],
-                            &vec![0.5; f0.len()],
-                            &vec![0.5; f0.len()],
-                            &vec![0.5; f0.len()],
-                        );
-                    }
-                    SynthThreadMessage::Do => break,
-                }
+        let smooth_f0 = if let Some(f0) = f0 {
+            let f0_end = sum_length + PHRASE_PADDING * 2.0;
+            (0..(f0_end / MS_PER_FRAME) as usize)
+                .map(|i| interpolate(f0, i as f64 - PHRASE_PADDING / MS_PER_FRAME))
+                .collect()
+        } else {
+            let mut f0 = Vec::new();
+            for (i, current) in otos.iter().enumerate() {
+                let f0_end = if i == otos.len() - 1 {
+                    current.position + current.length + PHRASE_PADDING * 2.0
+                } else {
+                    current.position + current.length + PHRASE_PADDING
+                };
+                f0.resize((f0_end / MS_PER_FRAME) as usize, current.freq);
             }
+            smooth(&f0, 10)
+        };
+        let mut curves = RenderCurves::new(smooth_f0, style_settings);
+        curves.apply_rules(&style_settings.curve_rules, &curve_spans(&otos));
+
+        let (message_sender, message_receiver) = std::sync::mpsc::channel::<SynthThreadMessage>();
 
-            info!("Synthesizing...");
+        let wav_task = spawn_synth_thread(message_receiver, cancelled.clone());
 
-            synthesizer.synth()
-        });
+        let mut segment: Option<Segment> = None;
 
         for (i, (current, con_vel, adjusted_param)) in
             izip!(otos.iter(), con_vels.iter(), adjusted_params.iter()).enumerate()
         {
+            if cancelled.load(Ordering::Relaxed) {
+                break;
+            }
             let span = tracing::debug_span!("mora", oto = %current.alias);
             let _guard = span.enter();
 
@@ -355,60 +1107,43 @@ pub async fn post_synthesis(
                 con_vel,
                 con_vel_to_factor(*con_vel)
             );
-            let start =
-                sum_length + PHRASE_PADDING + adjusted_param.shift() - adjusted_param.preutter;
-            let length = ((current.mora.vowel_length
-                + otos
-                    .get(i + 1)
-                    .map_or(0.0, |next| next.mora.consonant_length.unwrap_or(0.0)))
-                * 1000.0) as f64;
-            sum_length += length;
-
             let Some(oto) = &current.oto else {
+                // 無音で区切って合成すると、キャンセルされた時に途中で止められる
+                if let Some(segment) = segment.take() {
+                    if !send_segment(&message_sender, &curves, segment) {
+                        break;
+                    }
+                }
                 continue;
             };
             let oto_data = current.oto_data.as_ref().unwrap();
 
-            let skip = adjusted_param.skip.max(0.0) - start.min(0.0);
-            let start = start.max(0.0);
-
-            let adjusted_length = length
-                + adjusted_param.preutter
-                + adjusted_params
-                    .get(i + 1)
-                    .map_or(0.0, |next_adjusted_params| {
-                        next_adjusted_params.overlap - next_adjusted_params.preutter
-                    });
-
-            let mut next_fade = if i < otos.len() - 1 {
-                let next_adjusted_params = &adjusted_params[i + 1];
-                next_adjusted_params.fade()
-            } else {
-                0.0
-            };
-
-            let mut fade = if i == 0 { 0.0 } else { adjusted_param.fade() };
-
-            let volume = if fade + next_fade > adjusted_length {
-                warn!(
-                    "Fade length exceeds adjusted length: {:?} + {:?} > {:?}",
-                    fade, next_fade, adjusted_length
-                );
+            let (start, skip) = adjusted_param.start_and_skip(current.position);
+            let next_adjusted_param = adjusted_params.get(i + 1);
+            let adjusted_length = adjusted_param.length(current.length, next_adjusted_param);
 
-                let volume = ((adjusted_length - next_fade) / fade).clamp(0.0, 1.0);
-                fade = (adjusted_length - next_fade).max(0.0);
-                next_fade = (adjusted_length - fade).max(0.0);
+            let mut next_fade = next_adjusted_param.map_or(0.0, AdjustedParam::fade);
+            // 語尾は後ろが無音なので、自然に消えるようにフェードアウトさせる
+            if current.kind == UnitKind::Ending {
+                next_fade = next_fade.max(adjusted_length * ENDING_FADE_RATE);
+            }
+            let (fade, next_fade, volume) =
+                fit_fades(adjusted_param.fade(), next_fade, adjusted_length);
 
-                volume
-            } else {
-                1.0
-            };
+            // toneは半音単位に丸めてあるので、丸めた音を基準にして半音未満のずれもベンドに含める
+            let pitch_bend = note_pitch_bend(
+                style_settings,
+                &curves.f0,
+                start - skip,
+                adjusted_length + skip + 100.0,
+                current.freq / 2.0_f32.powf(current.pitch.cents / 1200.0),
+            );
 
             let request = SynthRequest {
-                sample_fs: oto_data.header.sample_rate as i32,
+                sample_fs: oto_data.sample_rate as i32,
                 sample: oto_data.samples.clone(),
                 frq: oto_data.frq.clone(),
-                tone: current.note.0 as i32,
+                tone: current.pitch.note.0 as i32,
                 con_vel: *con_vel,
                 offset: oto.offset,
                 required_length: adjusted_length + skip + 100.0,
@@ -416,59 +1151,47 @@ pub async fn post_synthesis(
                 cut_off: oto.cut_off - skip * oto.cut_off.signum(),
                 volume: (100f64 * volume) * (audio_query.volume_scale as f64),
                 modulation: 0.0,
-                tempo: 0.0,
-                pitch_bend: vec![0],
-                flag_g: style_settings.formant_shift as _,
+                tempo: PITCH_BEND_TEMPO,
+                pitch_bend,
+                // 性別・張り・息・声の強さは曲線で渡すので、フラグは標準の値にする
+                flag_g: 0,
                 flag_o: 0,
                 flag_p: style_settings.peak_compression as _,
-                flag_mt: style_settings.tension as _,
-                flag_mb: style_settings.breathiness as _,
-                flag_mv: style_settings.voicing as _,
+                flag_mt: 0,
+                flag_mb: 0,
+                flag_mv: 100,
             };
 
-            message_sender
-                .send(SynthThreadMessage::Request(
-                    current.alias.clone(),
+            segment
+                .get_or_insert_with(Segment::default)
+                .push(SegmentRequest {
+                    alias: current.alias.clone(),
                     request,
                     start,
                     skip,
-                    adjusted_length,
+                    length: adjusted_length,
                     fade,
                     next_fade,
-                ))
-                .expect("Failed to send message");
-
-            if i == 0 {
-                f0.extend(vec![current.freq; (PHRASE_PADDING / MS_PER_FRAME) as usize]);
-            }
-
-            f0.extend(vec![current.freq; (length / MS_PER_FRAME) as usize]);
-
-            if i == otos.len() - 1 {
-                f0.extend(vec![current.freq; (PHRASE_PADDING / MS_PER_FRAME) as usize]);
-            }
-
-            prev_vowel.clone_from(&current.mora.vowel.to_lowercase());
+                });
         }
         info!("Synthesizing {:?}", aliases);
 
-        let smooth_f0 = smooth(&f0, 10);
-
-        message_sender
-            .send(SynthThreadMessage::F0(smooth_f0))
-            .expect("Failed to send message");
-
-        message_sender
-            .send(SynthThreadMessage::Do)
-            .expect("Failed to send message");
+        if let Some(segment) = segment.take() {
+            send_segment(&message_sender, &curves, segment);
+        }
+        // 合成スレッドが先に終了していた場合は、結果がNoneになる
+        let _ = message_sender.send(SynthThreadMessage::Finish);
 
-        wav_task.await.unwrap()
+        wav_task
+            .await
+            .unwrap()
+            .ok_or_else(|| Error::SynthesisCancelled)?
     };
 
     let pre_phoneme_length = (audio_query.pre_phoneme_length / audio_query.speed_scale) as f64;
     let post_phoneme_length = (audio_query.post_phoneme_length / audio_query.speed_scale) as f64;
 
-    let duration = pre_phoneme_length + sum_length + post_phoneme_length;
+    let duration = pre_phoneme_length + sum_length / 1000.0 + post_phoneme_length;
 
     let mut padded_wav = vec![0.0; (duration * worldline::SAMPLE_RATE as f64) as usize];
 
@@ -482,6 +1205,11 @@ pub async fn post_synthesis(
         padded_wav[index as usize] = sample;
     }
 
+    Ok(padded_wav)
+}
+
+/// 波形を、AudioQueryで指定された形式のWAVにする。
+pub fn encode_wav(audio_query: &AudioQueryModel, wav: Vec<f32>) -> Vec<u8> {
     let sample_rate = audio_query
         .output_sampling_rate
         .as_f64()
@@ -493,17 +1221,20 @@ pub async fn post_synthesis(
         wav
     };
 
-    let result = wav_io::write_to_bytes(
+    write_float_wav(&wav, sample_rate, audio_query.output_stereo)
+}
+
+/// 32bit floatのWAVを書き出す。ステレオの場合、`samples`はインターリーブされている必要がある。
+pub fn write_float_wav(samples: &Vec<f32>, sample_rate: u32, stereo: bool) -> Vec<u8> {
+    wav_io::write_to_bytes(
         &WavHeader {
             sample_format: wav_io::header::SampleFormat::Float,
-            channels: if audio_query.output_stereo { 2 } else { 1 },
+            channels: if stereo { 2 } else { 1 },
             sample_rate,
             bits_per_sample: 32,
             list_chunk: None,
         },
-        &wav,
+        samples,
     )
-    .unwrap();
-
-    Ok(result)
+    .unwrap()
 }
@@ -1,4 +1,4 @@
-use crate::ongen_settings::OngenSettings;
+use crate::{ongen_settings::OngenSettings, preset::Preset};
 use anyhow::Result;
 use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
@@ -8,6 +8,9 @@ use tracing::{error, info};
 use uuid::Uuid;
 
 static SETTINGS: OnceCell<Mutex<Settings>> = OnceCell::new();
+static FORMAT_VERSION: u8 = 2;
+/// 読み込んだ音声のキャッシュの上限（MB）の初期値。
+pub static DEFAULT_SAMPLE_CACHE_LIMIT: usize = 1024;
 
 pub fn get_settings_path() -> PathBuf {
     let name = if cfg!(not(debug_assertions)) {
@@ -28,15 +31,23 @@ pub struct Settings {
     pub paths: Vec<String>,
     pub ongen_limit: usize,
     pub ongen_settings: HashMap<Uuid, OngenSettings>,
+    pub presets: Vec<Preset>,
+    /// 作った周波数表を、キャッシュに加えて音源のフォルダにも書き込むかどうか。
+    pub write_frq: bool,
+    /// 読み込んだ音声をメモリに残しておく上限（MB）。
+    pub sample_cache_limit: usize,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
-            format_version: 1,
+            format_version: FORMAT_VERSION,
             paths: vec![],
             ongen_limit: 10,
             ongen_settings: HashMap::new(),
+            presets: vec![],
+            write_frq: false,
+            sample_cache_limit: DEFAULT_SAMPLE_CACHE_LIMIT,
         }
     }
 }
@@ -46,7 +57,16 @@ async fn load_settings_inner() -> Result<Settings> {
 
     let settings = tokio::fs::read_to_string(path).await?;
 
-    let settings = serde_json::from_str(&settings)?;
+    let mut settings: Settings = serde_json::from_str(&settings)?;
+
+    // 1 -> 2：プリセットが追加された（無い場合は空になる）
+    if settings.format_version < FORMAT_VERSION {
+        info!(
+            "Migrating settings: {} -> {}",
+            settings.format_version, FORMAT_VERSION
+        );
+        settings.format_version = FORMAT_VERSION;
+    }
 
     Ok(settings)
 }
@@ -0,0 +1,40 @@
+//! 音源のテキストファイル（character.txt、oto.iniなど）の文字コードの判定。
+use encoding_rs::{Encoding, SHIFT_JIS, UTF_8};
+
+/// BOM、`hint`（character.yamlの`text_file_encoding`）、UTF-8として読めるか、の順に文字コードを決めて読む。
+/// どれにも当てはまらない場合はShift_JISとして読む。
+pub fn decode_text(bytes: &[u8], hint: Option<&str>) -> String {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        return encoding
+            .decode_without_bom_handling(&bytes[bom_length..])
+            .0
+            .into_owned();
+    }
+    if let Some(encoding) = hint.and_then(|hint| Encoding::for_label(hint.trim().as_bytes())) {
+        return encoding.decode_without_bom_handling(bytes).0.into_owned();
+    }
+    if let Some(text) = UTF_8.decode_without_bom_handling_and_without_replacement(bytes) {
+        return text.into_owned();
+    }
+    SHIFT_JIS.decode_without_bom_handling(bytes).0.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_text() {
+        let text = "あ.wav=- あ,0,0,0,0,0";
+        let (sjis, _, _) = SHIFT_JIS.encode(text);
+        let utf8_bom = [b"\xEF\xBB\xBF".as_slice(), text.as_bytes()].concat();
+
+        assert_eq!(decode_text(text.as_bytes(), None), text);
+        assert_eq!(decode_text(&sjis, None), text);
+        assert_eq!(decode_text(&utf8_bom, None), text);
+        assert_eq!(decode_text(&sjis, Some("shift_jis")), text);
+        // BOMはヒントより優先される
+        assert_eq!(decode_text(&utf8_bom, Some("shift_jis")), text);
+        assert_eq!(decode_text(text.as_bytes(), Some("utf-8")), text);
+    }
+}
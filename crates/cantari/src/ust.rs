@@ -0,0 +1,514 @@
+//! UTAUの`.ust`と、OpenUtauの`.ustx`の読み込み。
+//! 合成に使う情報だけを読み、時間はすべてmsに直す。
+use anyhow::{anyhow, Context, Result};
+use encoding_rs::Encoding;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+static TICKS_PER_BEAT: f64 = 480.0;
+static DEFAULT_TEMPO: f64 = 120.0;
+static REST_LYRICS: [&str; 3] = ["R", "r", ""];
+// 値を取るフラグのうち、worldlineが使うもの。長いものから探す
+static FLAG_NAMES: [&str; 6] = ["Mt", "Mb", "Mv", "g", "O", "P"];
+
+/// ピッチ曲線の、次の点までの補間の仕方。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PitchShape {
+    /// S字。
+    #[default]
+    InOut,
+    Linear,
+    /// 始めがゆっくり。
+    In,
+    /// 終わりがゆっくり。
+    Out,
+}
+
+impl PitchShape {
+    fn apply(self, rate: f64) -> f64 {
+        use std::f64::consts::PI;
+        match self {
+            Self::InOut => (1.0 - (PI * rate).cos()) / 2.0,
+            Self::Linear => rate,
+            Self::In => 1.0 - (PI / 2.0 * rate).cos(),
+            Self::Out => (PI / 2.0 * rate).sin(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PitchPoint {
+    /// ノートの開始からの時間（ms）。
+    pub time: f64,
+    /// ノートの音高からのずれ（セント）。
+    pub cents: f64,
+    pub shape: PitchShape,
+}
+
+/// ノート毎のフラグ。指定されていないものはスタイルの設定を使う。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NoteFlags {
+    pub g: Option<i32>,
+    pub o: Option<i32>,
+    pub p: Option<i32>,
+    pub mt: Option<i32>,
+    pub mb: Option<i32>,
+    pub mv: Option<i32>,
+}
+
+impl NoteFlags {
+    /// `g-5Mt20`のようなフラグをパースする。知らないフラグは無視する。
+    pub fn parse(flags: &str) -> Self {
+        let mut parsed = Self::default();
+        let mut rest = flags;
+        while !rest.is_empty() {
+            let Some(name) = FLAG_NAMES.iter().find(|&&name| rest.starts_with(name)) else {
+                let mut chars = rest.chars();
+                chars.next();
+                rest = chars.as_str();
+                continue;
+            };
+            rest = &rest[name.len()..];
+            let value_length = rest
+                .char_indices()
+                .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && (c == '-' || c == '+'))))
+                .map_or(rest.len(), |(i, _)| i);
+            let value = rest[..value_length].parse::<i32>().ok();
+            rest = &rest[value_length..];
+            let Some(value) = value else {
+                continue;
+            };
+            match *name {
+                "g" => parsed.g = Some(value),
+                "O" => parsed.o = Some(value),
+                "P" => parsed.p = Some(value),
+                "Mt" => parsed.mt = Some(value),
+                "Mb" => parsed.mb = Some(value),
+                "Mv" => parsed.mv = Some(value),
+                _ => unreachable!(),
+            }
+        }
+        parsed
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SongNote {
+    /// 開始位置（ms）。
+    pub position: f64,
+    /// 長さ（ms）。
+    pub length: f64,
+    pub lyric: String,
+    pub note_num: u8,
+    /// 子音速度。
+    pub velocity: f64,
+    /// 音量（%）。
+    pub intensity: f64,
+    pub modulation: f64,
+    pub flags: NoteFlags,
+    /// 先行発声の上書き（ms）。
+    pub preutter: Option<f64>,
+    /// オーバーラップの上書き（ms）。
+    pub overlap: Option<f64>,
+    pub pitch_points: Vec<PitchPoint>,
+}
+
+impl SongNote {
+    pub fn end(&self) -> f64 {
+        self.position + self.length
+    }
+
+    /// ピッチ曲線の、`time`（ms）での音高（セント）。曲線の範囲外ならNoneを返す。
+    pub fn pitch_at(&self, time: f64) -> Option<f64> {
+        let time = time - self.position;
+        let base = self.note_num as f64 * 100.0;
+        let index = self
+            .pitch_points
+            .windows(2)
+            .position(|points| points[0].time <= time && time <= points[1].time)?;
+        let (start, end) = (self.pitch_points[index], self.pitch_points[index + 1]);
+        let rate = if end.time > start.time {
+            (time - start.time) / (end.time - start.time)
+        } else {
+            1.0
+        };
+        Some(base + start.cents + (end.cents - start.cents) * start.shape.apply(rate))
+    }
+}
+
+/// 休符を除いたノートの並び。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Song {
+    pub notes: Vec<SongNote>,
+}
+
+/// テンポの変化を考慮して、tickをmsに直す。
+struct TempoMap {
+    /// （位置（tick）、BPM）。位置の順に並んでいる。
+    tempos: Vec<(f64, f64)>,
+    resolution: f64,
+}
+
+impl TempoMap {
+    fn to_ms(&self, tick: f64) -> f64 {
+        let mut ms = 0.0;
+        for (i, &(position, bpm)) in self.tempos.iter().enumerate() {
+            let end = self
+                .tempos
+                .get(i + 1)
+                .map_or(f64::INFINITY, |next| next.0)
+                .min(tick);
+            if end <= position {
+                break;
+            }
+            ms += (end - position) / self.resolution * 60000.0 / bpm;
+        }
+        ms
+    }
+}
+
+/// プロジェクトファイルを読み込む。`[#`から始まるものはUST、それ以外はUSTXとして扱う。
+pub fn read_project(bytes: &[u8]) -> Result<Song> {
+    // BOMがある場合はその文字コードで読んでから判定する
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        let text = encoding.decode_without_bom_handling(&bytes[bom_length..]).0;
+        return if text.starts_with("[#") {
+            parse_ust(&text)
+        } else {
+            parse_ustx(&text)
+        };
+    }
+    if bytes.starts_with(b"[#") {
+        // 古いUSTはShift_JISで、`Charset=UTF-8`があればUTF-8
+        let is_utf8 = bytes
+            .windows(b"Charset=UTF-8".len())
+            .any(|x| x == b"Charset=UTF-8");
+        let text = if is_utf8 {
+            encoding_rs::UTF_8.decode(bytes).0
+        } else {
+            encoding_rs::SHIFT_JIS.decode(bytes).0
+        };
+        parse_ust(&text)
+    } else {
+        parse_ustx(&String::from_utf8_lossy(bytes))
+    }
+}
+
+/// `PBS`/`PBW`/`PBY`/`PBM`からピッチ曲線を作る。
+fn parse_pitch_points(note: &HashMap<&str, &str>) -> Vec<PitchPoint> {
+    let Some(pbs) = note.get("PBS") else {
+        return vec![];
+    };
+    let mut pbs = pbs.split([';', ',']);
+    let start_time = pbs.next().and_then(|x| x.trim().parse::<f64>().ok());
+    let Some(start_time) = start_time else {
+        return vec![];
+    };
+    let start_y = pbs
+        .next()
+        .and_then(|x| x.trim().parse::<f64>().ok())
+        .unwrap_or(0.0);
+    let split = |key: &str| -> Vec<&str> {
+        note.get(key)
+            .map_or(vec![], |x| x.split(',').map(str::trim).collect())
+    };
+    let widths = split("PBW");
+    let ys = split("PBY");
+    let shapes = split("PBM");
+
+    let shape_at = |i: usize| match shapes.get(i).copied() {
+        Some("s") => PitchShape::Linear,
+        Some("r") => PitchShape::Out,
+        Some("j") => PitchShape::In,
+        _ => PitchShape::InOut,
+    };
+    let mut points = vec![PitchPoint {
+        time: start_time,
+        cents: start_y * 10.0,
+        shape: shape_at(0),
+    }];
+    for (i, width) in widths.iter().enumerate() {
+        let width = width.parse::<f64>().unwrap_or(0.0);
+        let y = ys.get(i).and_then(|x| x.parse::<f64>().ok()).unwrap_or(0.0);
+        points.push(PitchPoint {
+            time: points[i].time + width,
+            cents: y * 10.0,
+            shape: shape_at(i + 1),
+        });
+    }
+    if points.len() < 2 {
+        return vec![];
+    }
+    points
+}
+
+/// UTAUの`.ust`をパースする。
+pub fn parse_ust(text: &str) -> Result<Song> {
+    let mut sections: Vec<(&str, HashMap<&str, &str>)> = vec![];
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+            sections.push((name, HashMap::new()));
+        } else if let Some((key, value)) = line.split_once('=') {
+            if let Some((_, section)) = sections.last_mut() {
+                section.insert(key, value);
+            }
+        }
+    }
+
+    let setting = sections
+        .iter()
+        .find(|(name, _)| *name == "#SETTING")
+        .map(|(_, section)| section);
+    let parse_setting = |key: &str| {
+        setting
+            .and_then(|x| x.get(key))
+            .and_then(|x| x.parse::<f64>().ok())
+    };
+    let mut tempo = parse_setting("Tempo").unwrap_or(DEFAULT_TEMPO);
+    let default_flags = setting.and_then(|x| x.get("Flags")).copied().unwrap_or("");
+
+    let mut notes = vec![];
+    let mut position = 0.0;
+    for (name, note) in &sections {
+        // ノートは`[#0000]`のような番号のセクション
+        if !name
+            .strip_prefix('#')
+            .is_some_and(|x| !x.is_empty() && x.chars().all(|c| c.is_ascii_digit()))
+        {
+            continue;
+        }
+        let parse = |key: &str| note.get(key).and_then(|x| x.trim().parse::<f64>().ok());
+        if let Some(note_tempo) = parse("Tempo") {
+            tempo = note_tempo;
+        }
+        let ticks = parse("Length").with_context(|| format!("No length in [{}]", name))?;
+        let length = ticks / TICKS_PER_BEAT * 60000.0 / tempo;
+        let lyric = note.get("Lyric").copied().unwrap_or("").trim();
+        if !REST_LYRICS.contains(&lyric) {
+            let note_num = parse("NoteNum").with_context(|| format!("No note in [{}]", name))?;
+            notes.push(SongNote {
+                position,
+                length,
+                lyric: lyric.to_string(),
+                note_num: note_num.clamp(0.0, 127.0) as u8,
+                velocity: parse("Velocity").unwrap_or(100.0),
+                intensity: parse("Intensity").unwrap_or(100.0),
+                modulation: parse("Modulation").unwrap_or(0.0),
+                flags: NoteFlags::parse(note.get("Flags").copied().unwrap_or(default_flags)),
+                preutter: parse("PreUtterance"),
+                overlap: parse("VoiceOverlap"),
+                pitch_points: parse_pitch_points(note),
+            });
+        }
+        position += length;
+    }
+
+    Ok(Song { notes })
+}
+
+#[derive(Debug, Deserialize)]
+struct Ustx {
+    #[serde(default)]
+    bpm: Option<f64>,
+    #[serde(default)]
+    tempos: Vec<UstxTempo>,
+    #[serde(default = "default_resolution")]
+    resolution: f64,
+    #[serde(default)]
+    voice_parts: Vec<UstxVoicePart>,
+}
+
+fn default_resolution() -> f64 {
+    TICKS_PER_BEAT
+}
+
+#[derive(Debug, Deserialize)]
+struct UstxTempo {
+    position: f64,
+    bpm: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct UstxVoicePart {
+    #[serde(default)]
+    position: f64,
+    #[serde(default)]
+    notes: Vec<UstxNote>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UstxNote {
+    position: f64,
+    duration: f64,
+    tone: i32,
+    lyric: String,
+    #[serde(default)]
+    pitch: Option<UstxPitch>,
+    #[serde(default)]
+    phoneme_expressions: Vec<UstxExpression>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UstxPitch {
+    #[serde(default)]
+    data: Vec<UstxPitchPoint>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UstxPitchPoint {
+    x: f64,
+    y: f64,
+    #[serde(default)]
+    shape: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct UstxExpression {
+    #[serde(default)]
+    index: Option<usize>,
+    abbr: String,
+    value: f64,
+}
+
+/// OpenUtauの`.ustx`をパースする。
+pub fn parse_ustx(text: &str) -> Result<Song> {
+    let ustx: Ustx = serde_yaml::from_str(text).map_err(|e| anyhow!("Invalid ustx: {}", e))?;
+
+    let mut tempos = ustx
+        .tempos
+        .iter()
+        .map(|tempo| (tempo.position, tempo.bpm))
+        .collect::<Vec<_>>();
+    tempos.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if !tempos.first().is_some_and(|first| first.0 <= 0.0) {
+        tempos.insert(0, (0.0, ustx.bpm.unwrap_or(DEFAULT_TEMPO)));
+    }
+    let tempo_map = TempoMap {
+        tempos,
+        resolution: ustx.resolution,
+    };
+
+    let mut notes = vec![];
+    for part in &ustx.voice_parts {
+        for note in &part.notes {
+            if REST_LYRICS.contains(&note.lyric.trim()) {
+                continue;
+            }
+            let start = part.position + note.position;
+            let position = tempo_map.to_ms(start);
+            // 最初の音素の表情だけを使う
+            let expression = |abbr: &str| {
+                note.phoneme_expressions
+                    .iter()
+                    .find(|x| x.abbr == abbr && x.index.unwrap_or(0) == 0)
+                    .map(|x| x.value)
+            };
+            notes.push(SongNote {
+                position,
+                length: tempo_map.to_ms(start + note.duration) - position,
+                lyric: note.lyric.trim().to_string(),
+                note_num: note.tone.clamp(0, 127) as u8,
+                velocity: expression("vel").unwrap_or(100.0),
+                intensity: expression("vol").unwrap_or(100.0),
+                modulation: expression("mod").unwrap_or(0.0),
+                flags: NoteFlags {
+                    g: expression("gen").map(|x| x as i32),
+                    ..Default::default()
+                },
+                preutter: None,
+                overlap: None,
+                pitch_points: note.pitch.as_ref().map_or(vec![], |pitch| {
+                    pitch
+                        .data
+                        .iter()
+                        .map(|point| PitchPoint {
+                            time: point.x,
+                            cents: point.y * 10.0,
+                            shape: match point.shape.as_str() {
+                                "l" => PitchShape::Linear,
+                                "i" => PitchShape::In,
+                                "o" => PitchShape::Out,
+                                _ => PitchShape::InOut,
+                            },
+                        })
+                        .collect()
+                }),
+            });
+        }
+    }
+    notes.sort_by(|a, b| a.position.total_cmp(&b.position));
+
+    Ok(Song { notes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_flags() {
+        assert_eq!(
+            NoteFlags::parse("g-5BRE10Mt20P86"),
+            NoteFlags {
+                g: Some(-5),
+                mt: Some(20),
+                p: Some(86),
+                ..Default::default()
+            }
+        );
+        assert_eq!(NoteFlags::parse("eY0"), NoteFlags::default());
+    }
+
+    #[test]
+    fn test_parse_ust() {
+        let song = parse_ust(
+            "[#VERSION]\nUST Version1.2\n[#SETTING]\nTempo=120.00\nFlags=g-5\n\
+             [#0000]\nLength=480\nLyric=R\nNoteNum=60\n\
+             [#0001]\nLength=480\nLyric=あ\nNoteNum=60\nPBS=-40;-20\nPBW=80\nPBM=s\n\
+             [#0002]\nLength=960\nLyric=a い\nNoteNum=62\nTempo=60\nFlags=Mt10\nVelocity=150\n\
+             [#TRACKEND]\n",
+        )
+        .unwrap();
+
+        assert_eq!(song.notes.len(), 2);
+        let first = &song.notes[0];
+        assert_eq!(first.position, 500.0);
+        assert_eq!(first.length, 500.0);
+        assert_eq!(first.flags.g, Some(-5));
+        assert_eq!(first.pitch_at(460.0), Some(5800.0));
+        assert_eq!(first.pitch_at(500.0), Some(5900.0));
+        assert_eq!(first.pitch_at(540.0), Some(6000.0));
+        assert_eq!(first.pitch_at(600.0), None);
+
+        let second = &song.notes[1];
+        assert_eq!(second.lyric, "a い");
+        assert_eq!(second.position, 1000.0);
+        assert_eq!(second.length, 2000.0);
+        assert_eq!(second.velocity, 150.0);
+        assert_eq!(second.flags.g, None);
+        assert_eq!(second.flags.mt, Some(10));
+    }
+
+    #[test]
+    fn test_read_project_with_bom() {
+        let song = read_project(
+            "\u{feff}[#SETTING]\nTempo=120.00\n[#0000]\nLength=480\nLyric=あ\nNoteNum=60\n[#TRACKEND]\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(song.notes.len(), 1);
+        assert_eq!(song.notes[0].lyric, "あ");
+    }
+
+    #[test]
+    fn test_tempo_map() {
+        let tempo_map = TempoMap {
+            tempos: vec![(0.0, 120.0), (960.0, 60.0)],
+            resolution: 480.0,
+        };
+        assert_eq!(tempo_map.to_ms(480.0), 500.0);
+        assert_eq!(tempo_map.to_ms(960.0), 1000.0);
+        assert_eq!(tempo_map.to_ms(1440.0), 2000.0);
+    }
+}
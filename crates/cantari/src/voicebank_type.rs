@@ -0,0 +1,175 @@
+use regex_macro::regex;
+use serde::{Deserialize, Serialize};
+
+/// 音源の種類。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VoicebankType {
+    /// 単独音
+    Cv,
+    /// 連続音
+    Vcv,
+    /// CVVC
+    Cvvc,
+    /// 混在
+    Mixed,
+}
+
+// これ未満しか見つからなかった種類のエイリアスは、例外的なものとして無視する
+static MIN_ALIAS_COUNT: usize = 10;
+
+impl VoicebankType {
+    /// エイリアスの一覧から音源の種類を推定する。
+    pub fn detect<'a>(aliases: impl IntoIterator<Item = &'a str>) -> Self {
+        // ローマ字の連続音（`a ka`）は母音を含むことで、VC（`a k`）と区別する
+        let vcv_pattern = regex!(r"^[aiueonN] ([ぁ-ゖァ-ヺ]|[a-z]*[aiueo])");
+        let vc_pattern = regex!(r"^[aiueonN] [a-z]+");
+        let cv_pattern = regex!(r"^(- )?([ぁ-ゖァ-ヺ]|[a-z]*[aiueo])");
+
+        let mut vcv = 0;
+        let mut vc = 0;
+        let mut cv = 0;
+        for alias in aliases {
+            if vcv_pattern.is_match(alias) {
+                vcv += 1;
+            } else if vc_pattern.is_match(alias) {
+                vc += 1;
+            } else if cv_pattern.is_match(alias) {
+                cv += 1;
+            }
+        }
+
+        let has_vcv = vcv >= MIN_ALIAS_COUNT;
+        let has_vc = vc >= MIN_ALIAS_COUNT;
+        // 連続音でもフレーズ頭の`- か`は単独音と同じ形なので、連続音より多い時だけ単独音があるとみなす
+        let has_cv = cv >= MIN_ALIAS_COUNT && (!has_vcv || cv > vcv);
+
+        match (has_vcv, has_vc, has_cv) {
+            (true, true, _) => Self::Mixed,
+            (true, false, true) => Self::Mixed,
+            (true, false, false) => Self::Vcv,
+            (false, true, _) => Self::Cvvc,
+            (false, false, _) => Self::Cv,
+        }
+    }
+
+    /// 複数のフォルダの種類をまとめる。
+    pub fn merge(types: impl IntoIterator<Item = Self>) -> Self {
+        let mut types = types.into_iter();
+        let Some(first) = types.next() else {
+            return Self::Cv;
+        };
+        if types.all(|x| x == first) {
+            first
+        } else {
+            Self::Mixed
+        }
+    }
+
+    /// OpenUtauのPhonemizerの名前から音源の種類を推定する。分からない場合はNoneを返す。
+    pub fn from_phonemizer(phonemizer: &str) -> Option<Self> {
+        let name = phonemizer.rsplit('.').next().unwrap_or(phonemizer);
+        match name {
+            "JapaneseVCVPhonemizer" => Some(Self::Vcv),
+            "JapaneseCVVCPhonemizer" => Some(Self::Cvvc),
+            _ => None,
+        }
+    }
+
+    /// 前の母音から繋がるエイリアス（`a か`など）を使うかどうか。
+    pub fn uses_connected_aliases(self) -> bool {
+        !matches!(self, Self::Cv)
+    }
+
+    /// 母音から子音へのつなぎ（`a k`など）を使うかどうか。
+    pub fn uses_vc_aliases(self) -> bool {
+        matches!(self, Self::Cvvc | Self::Mixed)
+    }
+}
+
+impl std::fmt::Display for VoicebankType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Cv => "単独音",
+            Self::Vcv => "連続音",
+            Self::Cvvc => "CVVC",
+            Self::Mixed => "混在",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static KANA: [&str; 10] = ["か", "き", "く", "け", "こ", "さ", "し", "す", "せ", "そ"];
+
+    #[test]
+    fn test_detect_voicebank_type() {
+        let cv = KANA.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        let vcv = KANA
+            .iter()
+            .flat_map(|x| [format!("- {}", x), format!("a {}", x)])
+            .collect::<Vec<_>>();
+        let vc = ["k", "s", "t", "n", "h", "m", "y", "r", "w", "g"]
+            .iter()
+            .map(|x| format!("a {}", x))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            VoicebankType::detect(cv.iter().map(|x| x.as_str())),
+            VoicebankType::Cv
+        );
+        assert_eq!(
+            VoicebankType::detect(vcv.iter().map(|x| x.as_str())),
+            VoicebankType::Vcv
+        );
+        assert_eq!(
+            VoicebankType::detect(cv.iter().chain(vc.iter()).map(|x| x.as_str())),
+            VoicebankType::Cvvc
+        );
+        assert_eq!(
+            VoicebankType::detect(vcv.iter().chain(vc.iter()).map(|x| x.as_str())),
+            VoicebankType::Mixed
+        );
+    }
+
+    #[test]
+    fn test_detect_romaji_voicebank_type() {
+        let romaji = ["ka", "ki", "ku", "ke", "ko", "sa", "shi", "su", "se", "so"];
+        let vcv = romaji
+            .iter()
+            .flat_map(|x| [format!("- {}", x), format!("a {}", x)])
+            .collect::<Vec<_>>();
+        let vc = ["k", "s", "t", "n", "h", "m", "y", "r", "w", "g"]
+            .iter()
+            .map(|x| format!("a {}", x))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            VoicebankType::detect(romaji.iter().copied()),
+            VoicebankType::Cv
+        );
+        assert_eq!(
+            VoicebankType::detect(vcv.iter().map(|x| x.as_str())),
+            VoicebankType::Vcv
+        );
+        assert_eq!(
+            VoicebankType::detect(romaji.iter().copied().chain(vc.iter().map(|x| x.as_str()))),
+            VoicebankType::Cvvc
+        );
+    }
+
+    #[test]
+    fn test_merge_voicebank_type() {
+        assert_eq!(
+            VoicebankType::merge([VoicebankType::Vcv, VoicebankType::Vcv]),
+            VoicebankType::Vcv
+        );
+        assert_eq!(
+            VoicebankType::merge([VoicebankType::Cv, VoicebankType::Vcv]),
+            VoicebankType::Mixed
+        );
+    }
+}
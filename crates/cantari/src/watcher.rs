@@ -0,0 +1,277 @@
+//! 音源のファイルの変更を監視し、変わった音源だけを読み込み直す。
+use crate::{
+    ongen::{find_roots, setup_ongen, Ongen, ONGEN},
+    oto::invalidate_sample_cache,
+    settings::{load_settings, write_settings},
+};
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tracing::{info, info_span, warn, Instrument};
+use uuid::Uuid;
+
+static WATCH_INTERVAL: Duration = Duration::from_secs(2);
+// 音源のフォルダを探し直す間隔（`WATCH_INTERVAL`の回数）。それ以外の時は、知っているファイルだけを見る
+static RESCAN_TICKS: u32 = 15;
+// 変更を監視するファイル。サンプル（wav、frq）の変更は、`Oto::read`が更新日時を見て読み込み直す
+static WATCHED_FILES: [&str; 4] = ["character.txt", "character.yaml", "oto.ini", "prefix.map"];
+
+/// 音源のフォルダ毎の、監視するファイルの更新日時。
+type Snapshot = HashMap<PathBuf, BTreeMap<PathBuf, SystemTime>>;
+
+#[derive(Debug, Default, PartialEq)]
+struct Changes {
+    /// 音源のフォルダが増えたり減ったりしたかどうか。
+    roots_changed: bool,
+    /// ファイルが変わった音源のフォルダ。
+    modified_roots: Vec<PathBuf>,
+}
+
+fn scan_root(root: &Path) -> BTreeMap<PathBuf, SystemTime> {
+    // oto.iniは`Ongen::new`と同じ深さまで探す
+    walkdir::WalkDir::new(root)
+        .max_depth(3)
+        .into_iter()
+        .flatten()
+        .filter(|entry| {
+            entry.file_type().is_file()
+                && WATCHED_FILES.iter().any(|name| entry.file_name() == *name)
+        })
+        .filter_map(|entry| {
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            Some((entry.path().to_path_buf(), modified))
+        })
+        .collect()
+}
+
+/// 前回見つけたファイルと、音源のフォルダ直下の監視するファイルの更新日時だけを調べ直す。
+fn stat_root(root: &Path, known: &BTreeMap<PathBuf, SystemTime>) -> BTreeMap<PathBuf, SystemTime> {
+    known
+        .keys()
+        .cloned()
+        .chain(WATCHED_FILES.iter().map(|name| root.join(name)))
+        .filter_map(|path| {
+            let modified = std::fs::metadata(&path).ok()?.modified().ok()?;
+            Some((path, modified))
+        })
+        .collect()
+}
+
+fn restat(snapshot: &Snapshot) -> Snapshot {
+    snapshot
+        .iter()
+        .map(|(root, files)| (root.clone(), stat_root(root, files)))
+        .collect()
+}
+
+fn scan(paths: &[String]) -> Snapshot {
+    find_roots(paths)
+        .into_iter()
+        .map(|root| {
+            let files = scan_root(&root);
+            (root, files)
+        })
+        .collect()
+}
+
+fn diff(old: &Snapshot, new: &Snapshot) -> Changes {
+    let roots_changed = old.len() != new.len() || old.keys().any(|root| !new.contains_key(root));
+    let mut modified_roots = new
+        .iter()
+        .filter(|(root, files)| old.get(*root).is_some_and(|old_files| old_files != *files))
+        .map(|(root, _)| root.clone())
+        .collect::<Vec<_>>();
+    modified_roots.sort();
+    Changes {
+        roots_changed,
+        modified_roots,
+    }
+}
+
+/// `root`の音源を読み込み直し、他の音源はそのままで入れ替える。
+async fn reload_ongen(root: &Path) {
+    let old_uuid = {
+        let ongens = ONGEN.get().unwrap().read().await;
+        ongens
+            .values()
+            .find(|ongen| ongen.root == root)
+            .map(|ongen| ongen.uuid)
+    };
+    let Some(old_uuid) = old_uuid else {
+        // 上限などで読み込まれていない音源
+        return;
+    };
+
+    // 読み込み中も合成できるように、ロックの外で読み込む
+    let other_uuids = {
+        let ongens = ONGEN.get().unwrap().read().await;
+        ongens
+            .keys()
+            .filter(|uuid| **uuid != old_uuid)
+            .copied()
+            .collect::<Vec<Uuid>>()
+    };
+    let ongen = match Ongen::new(
+        root.to_path_buf(),
+        other_uuids.iter().collect::<Vec<&Uuid>>().as_slice(),
+    )
+    .await
+    {
+        Ok(ongen) => ongen,
+        Err(e) => {
+            warn!("Failed to reload ongen at {:?}: {}", root, e);
+            return;
+        }
+    };
+    invalidate_sample_cache(root);
+
+    // 名前が変わるとUUIDも変わるので、設定が無ければ作る
+    let mut settings = load_settings().await;
+    if let Entry::Vacant(entry) = settings.ongen_settings.entry(ongen.uuid) {
+        info!("Adding default settings for {}", ongen.name());
+        entry.insert(Default::default());
+        write_settings(&settings).await;
+    }
+
+    info!(
+        "Reloaded ongen: {} ({}, {})",
+        ongen.name(),
+        ongen.uuid,
+        ongen.id()
+    );
+    let mut ongens = ONGEN.get().unwrap().write().await;
+    let mut new_ongens = ongens.clone();
+    new_ongens.remove(&old_uuid);
+    new_ongens.insert(ongen.uuid, ongen);
+    *ongens = new_ongens;
+}
+
+/// 音源のファイルの変更を監視し続ける。`setup_ongen`の後に呼ぶ。
+pub async fn watch_ongens() {
+    let mut paths = load_settings().await.paths;
+    let mut snapshot = {
+        let paths = paths.clone();
+        tokio::task::spawn_blocking(move || scan(&paths))
+            .await
+            .unwrap_or_default()
+    };
+    info!("Watching {} ongen folders", snapshot.len());
+
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    let mut ticks = 0u32;
+    loop {
+        interval.tick().await;
+        ticks = ticks.wrapping_add(1);
+
+        let new_snapshot = if ticks.is_multiple_of(RESCAN_TICKS) {
+            let new_paths = load_settings().await.paths;
+            let new_snapshot = {
+                let new_paths = new_paths.clone();
+                tokio::task::spawn_blocking(move || scan(&new_paths)).await
+            };
+            match new_snapshot {
+                // パスの変更は`put_settings`で読み込み直しているので、監視の対象を変えるだけにする
+                Ok(new_snapshot) if new_paths != paths => {
+                    paths = new_paths;
+                    snapshot = new_snapshot;
+                    continue;
+                }
+                Ok(new_snapshot) => new_snapshot,
+                Err(e) => {
+                    warn!("Failed to scan ongen folders: {}", e);
+                    continue;
+                }
+            }
+        } else {
+            let old_snapshot = snapshot.clone();
+            match tokio::task::spawn_blocking(move || restat(&old_snapshot)).await {
+                Ok(new_snapshot) => new_snapshot,
+                Err(e) => {
+                    warn!("Failed to check ongen files: {}", e);
+                    continue;
+                }
+            }
+        };
+
+        let changes = diff(&snapshot, &new_snapshot);
+        snapshot = new_snapshot;
+        if changes.roots_changed {
+            info!("Ongen folders changed, reloading all ongens");
+            setup_ongen().await;
+            continue;
+        }
+        for root in changes.modified_roots {
+            let span = info_span!("reload", root = %root.display());
+            reload_ongen(&root).instrument(span).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(entries: &[(&str, &str, u64)]) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        for (root, file, modified) in entries {
+            snapshot.entry(PathBuf::from(root)).or_default().insert(
+                PathBuf::from(root).join(file),
+                SystemTime::UNIX_EPOCH + Duration::from_secs(*modified),
+            );
+        }
+        snapshot
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = snapshot(&[("a", "oto.ini", 0), ("b", "oto.ini", 0)]);
+
+        assert_eq!(diff(&old, &old), Changes::default());
+        assert_eq!(
+            diff(&old, &snapshot(&[("a", "oto.ini", 1), ("b", "oto.ini", 0)])),
+            Changes {
+                roots_changed: false,
+                modified_roots: vec![PathBuf::from("a")],
+            }
+        );
+        assert_eq!(
+            diff(
+                &old,
+                &snapshot(&[
+                    ("a", "oto.ini", 0),
+                    ("b", "oto.ini", 0),
+                    ("b", "prefix.map", 0)
+                ])
+            ),
+            Changes {
+                roots_changed: false,
+                modified_roots: vec![PathBuf::from("b")],
+            }
+        );
+        assert!(diff(&old, &snapshot(&[("a", "oto.ini", 0)])).roots_changed);
+        assert!(diff(&old, &snapshot(&[("a", "oto.ini", 0), ("c", "oto.ini", 0)])).roots_changed);
+    }
+
+    #[test]
+    fn test_stat_root() {
+        let root = std::env::temp_dir().join(format!("cantari-watcher-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("character.txt"), "name=test").unwrap();
+        std::fs::write(root.join("sub").join("oto.ini"), "").unwrap();
+
+        let scanned = scan_root(&root);
+        assert_eq!(scanned.len(), 2);
+        assert_eq!(stat_root(&root, &scanned), scanned);
+
+        // 直下に増えたファイルは見つかり、消えたファイルは無くなる
+        std::fs::write(root.join("prefix.map"), "").unwrap();
+        std::fs::remove_file(root.join("sub").join("oto.ini")).unwrap();
+        let stated = stat_root(&root, &scanned);
+        assert!(stated.contains_key(&root.join("prefix.map")));
+        assert!(!stated.contains_key(&root.join("sub").join("oto.ini")));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,41 @@
+use crate::phrase_synth::LIB;
+
+/// F0の推定方法。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum F0Method {
+    #[default]
+    Dio,
+    Harvest,
+}
+
+impl F0Method {
+    fn into_sys(self) -> i32 {
+        match self {
+            Self::Dio => 0,
+            Self::Harvest => 1,
+        }
+    }
+}
+
+/// `frame_period`ms毎のF0を推定する。無声のフレームは0になる。
+pub fn f0(samples: &[f32], fs: i32, frame_period: f64, method: F0Method) -> Vec<f64> {
+    if samples.is_empty() {
+        return vec![];
+    }
+    let mut samples = samples.to_vec();
+    let mut f0 = std::ptr::null_mut();
+    unsafe {
+        let len = LIB.F0(
+            samples.as_mut_ptr(),
+            samples.len() as i32,
+            fs,
+            frame_period,
+            method.into_sys(),
+            &mut f0,
+        );
+        if f0.is_null() || len <= 0 {
+            return vec![];
+        }
+        std::slice::from_raw_parts(f0, len as usize).to_vec()
+    }
+}
@@ -1,7 +1,9 @@
+mod f0;
 mod phrase_synth;
 mod synth_request;
 pub mod sys;
 
+pub use f0::{f0, F0Method};
 pub use phrase_synth::PhraseSynth;
 pub use synth_request::SynthRequest;
 
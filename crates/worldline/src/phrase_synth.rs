@@ -3,7 +3,7 @@ use dlopen2::wrapper::Container;
 use once_cell::sync::Lazy;
 use tracing::info;
 
-static LIB: Lazy<Container<sys::WorldlineSys>> = Lazy::new(|| {
+pub(crate) static LIB: Lazy<Container<sys::WorldlineSys>> = Lazy::new(|| {
     let lib_name = if cfg!(target_os = "windows") {
         "worldline.dll"
     } else if cfg!(target_os = "macos") {